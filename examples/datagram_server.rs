@@ -1,9 +1,7 @@
 #[macro_use]
 extern crate log;
 
-use solitude::{DatagramMessage, Session};
-
-use tokio::net::UdpSocket;
+use solitude::I2pDatagramSocket;
 
 use anyhow::Result;
 
@@ -14,39 +12,23 @@ async fn main() -> Result<()> {
 		.parse_env("RUST_LOG")
 		.init();
 
-	let udp_socket = UdpSocket::bind("127.0.0.1:0").await?;
-	udp_socket.connect("127.0.0.1:7655").await?;
-
-	let port = udp_socket.local_addr()?.port();
-
-	let mut session = Session::new("echo_server", solitude::SessionStyle::Datagram).await?;
-	session.forward("127.0.0.1", port).await?;
-
-	info!("Listening on i2p at {}", session.address()?);
+	let socket = I2pDatagramSocket::bind("echo_server").await?;
 
-	let mut buffer = [0u8; 2048];
+	info!("Listening on i2p at {}", socket.session().address()?);
 
 	loop {
 		info!("Waiting to receive");
-		let frame = udp_socket.recv(&mut buffer).await?;
-		let buffer = &mut buffer[..frame];
 
-		debug!("Received datagram bytes: {:02x?}", buffer);
-
-		let received_datagram = match DatagramMessage::from_bytes("echo_server", buffer) {
-			Ok(received_datagram) => received_datagram,
-			Err(_) => {
-				debug!("Received a datagram but could not deserialize it");
+		let (contents, destination) = match socket.recv_from().await {
+			Ok(received) => received,
+			Err(error) => {
+				debug!("Received a datagram but could not deserialize it: {}", error);
 				continue;
 			}
 		};
 
-		info!("Received datagram: {}", std::str::from_utf8(&received_datagram.contents)?);
-
-		let datagram_to_send = DatagramMessage::new("echo_server", &received_datagram.destination, received_datagram.contents);
-
-		let datagram_to_send_bytes = datagram_to_send.serialize();
+		info!("Received datagram: {}", String::from_utf8_lossy(&contents));
 
-		udp_socket.send(&datagram_to_send_bytes).await?;
+		socket.send_to(&contents, &destination).await?;
 	}
 }
@@ -23,7 +23,7 @@ async fn main() -> Result<()> {
 	let server_name = arguments[1].to_owned();
 
 	info!("Creating a SAM v3 session");
-	let mut session = Session::new("stream_client_example", SessionStyle::Stream).await?;
+	let session = Session::new("stream_client_example", SessionStyle::Stream).await?;
 	let destination = session.look_up(server_name).await?;
 
 	info!("Connecting to server");
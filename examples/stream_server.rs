@@ -4,7 +4,7 @@ use tokio::io::BufReader;
 #[macro_use]
 extern crate log;
 
-use solitude::{Session, SessionStyle, StreamInfo};
+use solitude::{Session, SessionStyle};
 
 use anyhow::Result;
 
@@ -15,26 +15,19 @@ async fn main() -> Result<()> {
 		.parse_env("RUST_LOG")
 		.init();
 
-	info!("Creating tcp server");
-	let tcp_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
-	let port = tcp_listener.local_addr()?.port();
-
 	info!("Creating SAMv3 session");
-	let mut session = Session::new("stream_server_example", SessionStyle::Stream).await?;
-	info!("Forwarding tcp server to i2p");
-	session.forward("127.0.0.1", port).await?;
+	let session = Session::new("stream_server_example", SessionStyle::Stream).await?;
+	info!("Accepting streams from i2p");
+	let mut listener = session.listen().await?;
 
 	info!("Listening on {}", session.address()?);
 
-	for (mut stream, _address) in tcp_listener.accept().await {
-		let mut reader = BufReader::new(&mut stream);
-		let stream_info = StreamInfo::from_bufread(&mut reader).await?;
+	loop {
+		let (stream, peer_destination) = listener.accept().await?;
 
 		let mut data = String::new();
-		reader.read_line(&mut data).await?;
+		BufReader::new(stream).read_line(&mut data).await?;
 
-		info!("received: \"{}\" from {}", data, stream_info.destination);
+		info!("received: \"{}\" from {}", data, peer_destination);
 	}
-
-	Ok(())
 }
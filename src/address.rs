@@ -0,0 +1,193 @@
+use crate::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+use data_encoding::{Encoding, Specification};
+
+const B32_SUFFIX: &str = ".b32.i2p";
+
+/// Length of the base32 part of an address made from a destination hash.
+const HASH_ADDRESS_LENGTH: usize = 52;
+
+/// Shortest base32 part of an encrypted leaseset address, which holds at least flags, two signature types and a key.
+const MIN_BLINDED_ADDRESS_LENGTH: usize = 56;
+
+const FLAG_TWO_BYTE_SIGNATURE_TYPES: u8 = 0x01;
+const FLAG_SECRET_REQUIRED: u8 = 0x02;
+const FLAG_CLIENT_AUTH_REQUIRED: u8 = 0x04;
+
+/// A `.b32.i2p` address.
+///
+/// Usually the SHA-256 of a destination, in 52 characters. Services with encrypted leasesets instead have 56 or more
+/// characters encoding their signing public key, from which the key their leaseset is blinded with is derived each day;
+/// these are often called b33 addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct B32Address {
+	bytes: Vec<u8>,
+}
+
+impl B32Address {
+	/// Whether this is an encrypted leaseset address rather than a destination hash.
+	pub fn is_blinded(&self) -> bool {
+		self.bytes.len() != 32
+	}
+
+	/// The destination hash, unless this is an encrypted leaseset address.
+	pub fn hash(&self) -> Option<[u8; 32]> {
+		self.bytes.as_slice().try_into().ok()
+	}
+
+	/// The decoded base32 part of the address.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	/// The unblinded key and flags an encrypted leaseset address encodes.
+	pub fn b33_key(&self) -> Option<B33Key> {
+		if self.is_blinded() {
+			B33Key::decode(&self.bytes).ok()
+		} else {
+			None
+		}
+	}
+
+	/// Whether the address points at `destination`; encrypted leaseset addresses never match directly.
+	pub fn matches(&self, destination: &Destination) -> bool {
+		self.hash() == Some(destination.hash())
+	}
+}
+
+impl FromStr for B32Address {
+	type Err = Error;
+
+	/// Parses an address, ignoring case.
+	fn from_str(address: &str) -> Result<Self> {
+		let lowercase = address.to_ascii_lowercase();
+
+		let encoded = lowercase
+			.strip_suffix(B32_SUFFIX)
+			.ok_or_else(|| Error::Address(format!("{} does not end in {}", address, B32_SUFFIX)))?;
+
+		if encoded.len() != HASH_ADDRESS_LENGTH && encoded.len() < MIN_BLINDED_ADDRESS_LENGTH {
+			return Err(Error::Address(format!("{} has the wrong length for a b32 address", address)));
+		}
+
+		let bytes = base32_encoding()
+			.decode(encoded.as_bytes())
+			.map_err(|error| Error::Address(format!("{} is not valid base32: {}", address, error)))?;
+
+		if bytes.len() != 32 {
+			B33Key::decode(&bytes)?;
+		}
+
+		Ok(Self { bytes })
+	}
+}
+
+impl fmt::Display for B32Address {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(formatter, "{}{}", base32_encoding().encode(&self.bytes), B32_SUFFIX)
+	}
+}
+
+impl From<&Destination> for B32Address {
+	fn from(destination: &Destination) -> Self {
+		Self {
+			bytes: destination.hash().to_vec(),
+		}
+	}
+}
+
+impl From<&B33Key> for B32Address {
+	fn from(b33_key: &B33Key) -> Self {
+		Self { bytes: b33_key.encode() }
+	}
+}
+
+/// What an encrypted leaseset address encodes: the service's signing public key, and what clients need besides it.
+///
+/// The key is not blinded. Routers derive the blinded key the leaseset is published under from it, and from the date.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct B33Key {
+	/// Signature type of the destination, and of `signing_public_key`.
+	pub signature_type: SignatureType,
+	/// Signature type of the blinded keys derived from `signing_public_key`.
+	pub blinded_signature_type: SignatureType,
+	pub signing_public_key: Vec<u8>,
+	/// Whether clients need the service's lookup secret.
+	pub secret_required: bool,
+	/// Whether clients need a DH or PSK key the service has authorized.
+	pub client_auth_required: bool,
+}
+
+impl B33Key {
+	/// Decodes the bytes of a b33 address, whose first three bytes are masked with the CRC-32 of the rest.
+	fn decode(bytes: &[u8]) -> Result<Self> {
+		if bytes.len() < 4 {
+			return Err(Error::Address("encrypted leaseset address is too short".to_owned()));
+		}
+
+		let crc = crc32fast::hash(&bytes[3..]).to_le_bytes();
+		let flags = bytes[0] ^ crc[0];
+
+		if flags & FLAG_TWO_BYTE_SIGNATURE_TYPES != 0 {
+			return Err(Error::Address(
+				"two-byte signature types in b33 addresses are not supported".to_owned(),
+			));
+		}
+
+		let signature_type = |byte: u8| {
+			SignatureType::from_code(byte.into()).ok_or_else(|| Error::Address(format!("unknown signature type {} in b33 address", byte)))
+		};
+
+		let b33_key = Self {
+			signature_type: signature_type(bytes[1] ^ crc[1])?,
+			blinded_signature_type: signature_type(bytes[2] ^ crc[2])?,
+			signing_public_key: bytes[3..].to_vec(),
+			secret_required: flags & FLAG_SECRET_REQUIRED != 0,
+			client_auth_required: flags & FLAG_CLIENT_AUTH_REQUIRED != 0,
+		};
+
+		if b33_key.signing_public_key.len() != b33_key.signature_type.public_key_length() {
+			return Err(Error::Address(format!(
+				"b33 address holds a {} byte key for {}",
+				b33_key.signing_public_key.len(),
+				b33_key.signature_type
+			)));
+		}
+
+		Ok(b33_key)
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut flags = 0;
+
+		if self.secret_required {
+			flags |= FLAG_SECRET_REQUIRED;
+		}
+
+		if self.client_auth_required {
+			flags |= FLAG_CLIENT_AUTH_REQUIRED;
+		}
+
+		let mut bytes = vec![flags, self.signature_type.code() as u8, self.blinded_signature_type.code() as u8];
+		bytes.extend_from_slice(&self.signing_public_key);
+
+		let crc = crc32fast::hash(&bytes[3..]).to_le_bytes();
+
+		for (byte, mask) in bytes.iter_mut().zip(&crc[..3]) {
+			*byte ^= mask;
+		}
+
+		bytes
+	}
+}
+
+/// Lowercase, unpadded base32, as used in I2P addresses.
+fn base32_encoding() -> Encoding {
+	let mut specification = Specification::new();
+	specification.symbols.push_str("abcdefghijklmnopqrstuvwxyz234567");
+
+	specification.encoding().unwrap()
+}
@@ -0,0 +1,249 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Longest hostname I2P accepts, `.i2p` included.
+const MAX_HOSTNAME_LENGTH: usize = 67;
+
+/// Starts the properties of an entry, and command lines of their own.
+const PROPERTIES_MARKER: &str = "#!";
+
+/// One entry of an address book: a hostname, its destination and its `#!` properties, such as `date` or `sig`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostEntry {
+	pub name: String,
+	pub destination: Destination,
+	pub properties: BTreeMap<String, String>,
+}
+
+impl HostEntry {
+	pub fn new<S: Into<String>>(name: S, destination: Destination) -> Self {
+		Self {
+			name: name.into().to_ascii_lowercase(),
+			destination,
+			properties: BTreeMap::new(),
+		}
+	}
+}
+
+impl fmt::Display for HostEntry {
+	/// Writes the entry as a hosts.txt line, without its newline.
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(formatter, "{}={}", self.name, self.destination)?;
+
+		if !self.properties.is_empty() {
+			formatter.write_str(PROPERTIES_MARKER)?;
+			formatter.write_str(&format_properties(&self.properties))?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Hostnames and their destinations, kept in the `hosts.txt` format i2pd and Java I2P use.
+///
+/// Each line is `name=destination`, optionally followed by `#!key=value#key=value` properties. Lines that start with
+/// `#!` hold commands for subscribers, such as `#!action=remove#name=example.i2p`, which are not applied; other lines
+/// starting with `#` are comments. Lines that can't be read are skipped, as address books from subscriptions often carry some.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressBook {
+	entries: BTreeMap<String, HostEntry>,
+}
+
+/// A line of a hosts.txt file.
+enum Line {
+	Entry(HostEntry),
+	Command(BTreeMap<String, String>),
+}
+
+impl AddressBook {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Reads a hosts.txt file. Commands in it are ignored; see [`AddressBook::merge`].
+	pub fn parse(text: &str) -> Self {
+		let mut address_book = Self::new();
+
+		for line in lines(text) {
+			if let Line::Entry(entry) = line {
+				address_book.insert(entry);
+			}
+		}
+
+		address_book
+	}
+
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+		Ok(Self::parse(&std::fs::read_to_string(path)?))
+	}
+
+	/// Writes the book in hosts.txt format, sorted by hostname.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		Ok(std::fs::write(path, self.to_string())?)
+	}
+
+	/// Adds or replaces an entry, returning the one it replaced.
+	pub fn insert(&mut self, entry: HostEntry) -> Option<HostEntry> {
+		self.entries.insert(entry.name.clone(), entry)
+	}
+
+	pub fn remove(&mut self, name: &str) -> Option<HostEntry> {
+		self.entries.remove(&name.to_ascii_lowercase())
+	}
+
+	pub fn get(&self, name: &str) -> Option<&HostEntry> {
+		self.entries.get(&name.to_ascii_lowercase())
+	}
+
+	/// Finds the destination of a hostname, or of a b32 address if an entry has that destination.
+	pub fn look_up(&self, name: &str) -> Option<&Destination> {
+		if let Some(entry) = self.get(name) {
+			return Some(&entry.destination);
+		}
+
+		let address = name.parse::<B32Address>().ok()?;
+
+		self.entries
+			.values()
+			.map(|entry| &entry.destination)
+			.find(|destination| address.matches(destination))
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &HostEntry> {
+		self.entries.values()
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Merges a subscription feed in hosts.txt format, returning how many names it added.
+	///
+	/// As with I2P's address book, names already in the book keep their destination, so a feed can't take a name over.
+	/// Commands such as `#!action=remove#name=...` are ignored: their signatures aren't checked, and a feed that could
+	/// remove a name could then add it back with its own destination.
+	pub fn merge(&mut self, feed: &str) -> usize {
+		let mut added = 0;
+
+		for line in lines(feed) {
+			match line {
+				Line::Entry(entry) if !self.entries.contains_key(&entry.name) => {
+					self.insert(entry);
+					added += 1;
+				}
+				Line::Entry(_) => {}
+				Line::Command(properties) => debug!(
+					"address book ignored unverified subscription command {:?}",
+					properties.get("action")
+				),
+			}
+		}
+
+		added
+	}
+
+	/// Merges the subscription feed saved at `path`; see [`AddressBook::merge`].
+	pub fn subscribe<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+		Ok(self.merge(&std::fs::read_to_string(path)?))
+	}
+}
+
+impl fmt::Display for AddressBook {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for entry in self.entries.values() {
+			writeln!(formatter, "{}", entry)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl std::str::FromStr for AddressBook {
+	type Err = Error;
+
+	fn from_str(text: &str) -> Result<Self> {
+		Ok(Self::parse(text))
+	}
+}
+
+impl<'a> IntoIterator for &'a AddressBook {
+	type Item = &'a HostEntry;
+	type IntoIter = std::collections::btree_map::Values<'a, String, HostEntry>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.entries.values()
+	}
+}
+
+/// The lines of a hosts.txt file that could be read.
+fn lines(text: &str) -> impl Iterator<Item = Line> + '_ {
+	text.lines().filter_map(|line| {
+		let line = line.trim();
+
+		if let Some(properties) = line.strip_prefix(PROPERTIES_MARKER) {
+			return Some(Line::Command(parse_properties(properties)));
+		}
+
+		if line.is_empty() || line.starts_with('#') {
+			return None;
+		}
+
+		let entry = parse_entry(line);
+
+		if entry.is_none() {
+			warn!("address book skipped an invalid line: {}", line);
+		}
+
+		entry.map(Line::Entry)
+	})
+}
+
+fn parse_entry(line: &str) -> Option<HostEntry> {
+	let (entry, properties) = line.split_once(PROPERTIES_MARKER).unwrap_or((line, ""));
+	let (name, destination) = entry.split_once('=')?;
+
+	let name = name.trim().to_ascii_lowercase();
+
+	if !is_hostname(&name) {
+		return None;
+	}
+
+	Some(HostEntry {
+		name,
+		destination: Destination::from_base64(destination.trim()).ok()?,
+		properties: parse_properties(properties),
+	})
+}
+
+/// Whether `name` is a hostname an address book can hold, which b32 addresses are not.
+fn is_hostname(name: &str) -> bool {
+	name.len() <= MAX_HOSTNAME_LENGTH
+		&& name.ends_with(".i2p")
+		&& !name.ends_with(".b32.i2p")
+		&& name.split('.').all(|label| {
+			!label.is_empty() && !label.starts_with('-') && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+		})
+}
+
+fn parse_properties(properties: &str) -> BTreeMap<String, String> {
+	properties
+		.split('#')
+		.filter_map(|property| property.split_once('='))
+		.map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+		.collect()
+}
+
+fn format_properties(properties: &BTreeMap<String, String>) -> String {
+	properties
+		.iter()
+		.map(|(key, value)| format!("{}={}", key, value))
+		.collect::<Vec<_>>()
+		.join("#")
+}
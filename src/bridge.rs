@@ -0,0 +1,1178 @@
+//! A SAMv3 bridge that serves SAM clients over a pluggable [`Transport`] instead of an I2P router.
+
+use crate::datagram::parse_header_value;
+use crate::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Mutex as StdMutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::{JoinHandle, JoinSet};
+
+mod loopback;
+pub use loopback::LoopbackTransport;
+
+/// Highest SAM version the bridge speaks.
+const VERSION: (u32, u32) = (3, 3);
+
+/// How long an incoming stream waits for its session to accept it, like a router holding on to a SYN.
+const ACCEPT_WAIT: Duration = Duration::from_secs(5);
+
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Protocol of repliable datagrams. Every other protocol is raw.
+const DATAGRAM_PROTOCOL: u8 = 17;
+
+/// Protocol of raw datagrams from sessions that don't pick one.
+const RAW_PROTOCOL: u8 = 18;
+
+/// Datagrams waiting to be written to a control connection before more are dropped.
+const RECEIVED_QUEUE_LENGTH: usize = 64;
+
+/// The network a [`Bridge`] carries its sessions' traffic over.
+///
+/// A transport puts destinations online, opens streams between them, carries datagrams and resolves names. The bridge
+/// does the rest: the SAM protocol, sessions, and handing traffic to its clients.
+pub trait Transport: Send + Sync + 'static {
+	type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+	/// Puts the destination of `keys` online, reachable until the returned endpoint is dropped.
+	///
+	/// Fails with [`SamResult::DuplicatedDest`] if the destination is already online.
+	fn open(&self, keys: &PrivateKeys) -> impl Future<Output = Result<Endpoint<Self::Stream>>> + Send;
+
+	/// Opens a stream from `source` to `peer`, failing with [`SamResult::CantReachPeer`] if the peer is offline.
+	fn connect(
+		&self,
+		source: &Destination,
+		peer: &Destination,
+		from_port: u16,
+		to_port: u16,
+	) -> impl Future<Output = Result<Self::Stream>> + Send;
+
+	/// Sends a datagram to `peer`. Like UDP, a datagram the peer cannot take is dropped.
+	fn send(&self, peer: &Destination, datagram: Datagram) -> impl Future<Output = Result<()>> + Send;
+
+	/// Resolves a hostname or b32 address, along with any properties the name has.
+	fn look_up(&self, name: &str) -> impl Future<Output = Result<Option<NamingLookup>>> + Send;
+}
+
+/// Where the incoming traffic of an online destination arrives.
+#[derive(Debug)]
+pub struct Endpoint<S> {
+	pub streams: Receiver<IncomingStream<S>>,
+	pub datagrams: Receiver<Datagram>,
+}
+
+/// A stream opened by another destination.
+#[derive(Debug)]
+pub struct IncomingStream<S> {
+	pub stream: S,
+	pub peer: Destination,
+	pub from_port: u16,
+	pub to_port: u16,
+}
+
+/// A datagram between two destinations.
+///
+/// Repliable datagrams have protocol 17 and go to DATAGRAM sessions; any other protocol is raw, goes to RAW sessions,
+/// and does not reveal `source` to the client receiving it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datagram {
+	pub source: Destination,
+	pub from_port: u16,
+	pub to_port: u16,
+	pub protocol: u8,
+	pub contents: Vec<u8>,
+}
+
+/// Sees every command before the bridge handles it, and may hold it back or refuse it.
+pub trait Interceptor: Send + Sync + 'static {
+	fn intercept(&self, command: &SamMessage) -> Interception;
+}
+
+/// What an [`Interceptor`] does to a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interception {
+	/// Holds the command this long before handling it.
+	pub delay: Option<Duration>,
+	/// Refuses the command with this RESULT instead of handling it.
+	pub failure: Option<SamResult>,
+}
+
+/// A SAMv3 bridge serving clients on a TCP port and a UDP port, up to SAM 3.3.
+///
+/// It handles HELLO, DEST GENERATE, SESSION CREATE/ADD/REMOVE, STREAM CONNECT/ACCEPT/FORWARD, DATAGRAM and RAW SEND,
+/// NAMING LOOKUP, PING and QUIT. Datagram and raw sessions receive their datagrams on the UDP port set with PORT, or as
+/// DATAGRAM and RAW RECEIVED on their control connection without one. Sessions are carried by its [`Transport`].
+/// Dropping the bridge stops it.
+#[derive(Debug)]
+pub struct Bridge {
+	sam_address: SocketAddr,
+	datagram_address: SocketAddr,
+	tasks: Vec<JoinHandle<()>>,
+}
+
+impl Bridge {
+	/// Starts a bridge on ephemeral loopback ports.
+	pub async fn start<T: Transport>(transport: T) -> Result<Self> {
+		Self::builder(transport).start().await
+	}
+
+	pub fn builder<T: Transport>(transport: T) -> BridgeBuilder<T> {
+		BridgeBuilder::new(transport)
+	}
+
+	/// Where clients connect to send commands.
+	pub fn sam_address(&self) -> SocketAddr {
+		self.sam_address
+	}
+
+	/// Where clients send datagrams.
+	pub fn datagram_address(&self) -> SocketAddr {
+		self.datagram_address
+	}
+
+	/// Client configuration pointing at this bridge.
+	pub fn config(&self) -> SamConfig {
+		SamConfig {
+			sam_address: self.sam_address.to_string(),
+			datagram_address: self.datagram_address.to_string(),
+			..SamConfig::default()
+		}
+	}
+}
+
+impl Drop for Bridge {
+	fn drop(&mut self) {
+		for task in &self.tasks {
+			task.abort();
+		}
+	}
+}
+
+/// Configures and starts a [`Bridge`].
+pub struct BridgeBuilder<T> {
+	transport: T,
+	sam_address: String,
+	datagram_address: String,
+	interceptor: Option<Arc<dyn Interceptor>>,
+}
+
+impl<T: Transport> BridgeBuilder<T> {
+	pub fn new(transport: T) -> Self {
+		Self {
+			transport,
+			sam_address: "127.0.0.1:0".to_owned(),
+			datagram_address: "127.0.0.1:0".to_owned(),
+			interceptor: None,
+		}
+	}
+
+	/// `host:port` to accept commands on; by default an ephemeral loopback port.
+	pub fn sam_address<S: Into<String>>(mut self, address: S) -> Self {
+		self.sam_address = address.into();
+		self
+	}
+
+	/// `host:port` to receive datagrams on; by default an ephemeral loopback port.
+	pub fn datagram_address<S: Into<String>>(mut self, address: S) -> Self {
+		self.datagram_address = address.into();
+		self
+	}
+
+	pub fn interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+		self.interceptor = Some(interceptor);
+		self
+	}
+
+	/// Binds both ports and starts serving.
+	pub async fn start(self) -> Result<Bridge> {
+		let listener = TcpListener::bind(&self.sam_address).await?;
+		let udp = UdpSocket::bind(&self.datagram_address).await?;
+
+		let sam_address = listener.local_addr()?;
+		let datagram_address = udp.local_addr()?;
+
+		debug!(
+			"bridge is listening on {} and receiving datagrams on {}",
+			sam_address, datagram_address
+		);
+
+		let shared = Arc::new(Shared {
+			transport: self.transport,
+			interceptor: self.interceptor,
+			udp,
+			sessions: StdMutex::new(HashMap::new()),
+			control_connections: StdMutex::new(HashMap::new()),
+			versions: StdMutex::new(HashMap::new()),
+			connection_ids: AtomicU64::new(0),
+		});
+
+		let tasks = vec![
+			tokio::spawn(accept_connections(listener, shared.clone())),
+			tokio::spawn(receive_datagrams(shared)),
+		];
+
+		Ok(Bridge {
+			sam_address,
+			datagram_address,
+			tasks,
+		})
+	}
+}
+
+type Connection = BufReader<TcpStream>;
+
+struct Shared<T> {
+	transport: T,
+	interceptor: Option<Arc<dyn Interceptor>>,
+	udp: UdpSocket,
+	sessions: StdMutex<HashMap<String, BridgeSession>>,
+	/// Where DATAGRAM and RAW RECEIVED are queued for each connection.
+	control_connections: StdMutex<HashMap<u64, Sender<Vec<u8>>>>,
+	/// The SAM version each connection agreed on in HELLO.
+	versions: StdMutex<HashMap<u64, (u32, u32)>>,
+	connection_ids: AtomicU64,
+}
+
+struct BridgeSession {
+	destination: Destination,
+	style: String,
+	/// Port that streams and datagrams must be sent to, or 0 for any.
+	listen_port: u16,
+	/// The control connection that created the session, which ends it by closing.
+	owner: u64,
+	/// Where datagram and raw sessions receive their datagrams, if not on their control connection.
+	datagram_forward: Option<SocketAddr>,
+	/// Whether raw datagrams are received with a header line.
+	header: bool,
+	/// Protocol of raw datagrams sent without one.
+	protocol: u8,
+	acceptors: VecDeque<Acceptor>,
+	/// STREAM FORWARD target, whether it is silent, and the connection that asked for it.
+	stream_forward: Option<(SocketAddr, bool, u64)>,
+	/// Serves incoming traffic, on the session that put the destination online.
+	endpoint: Option<Task>,
+}
+
+/// A client connection waiting in STREAM ACCEPT.
+struct Acceptor {
+	stream: TcpStream,
+	silent: bool,
+}
+
+impl Acceptor {
+	/// Whether the client hung up while waiting. Peeking leaves anything it already sent for the stream.
+	fn is_closed(&self) -> bool {
+		let mut byte = [0u8; 1];
+		let mut context = Context::from_waker(Waker::noop());
+
+		matches!(
+			self.stream.poll_peek(&mut context, &mut ReadBuf::new(&mut byte)),
+			Poll::Ready(Ok(0) | Err(_))
+		)
+	}
+}
+
+/// Where an incoming stream goes.
+enum Taker {
+	Accepting(Acceptor),
+	Forwarding(SocketAddr, bool),
+}
+
+/// Aborts its task when dropped.
+struct Task(JoinHandle<()>);
+
+impl Drop for Task {
+	fn drop(&mut self) {
+		self.0.abort();
+	}
+}
+
+impl<T: Transport> Shared<T> {
+	fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, BridgeSession>> {
+		self.sessions.lock().unwrap()
+	}
+
+	/// The destination of session `service` if it has `style`.
+	fn destination_of(&self, service: Option<&str>, style: &str) -> Option<Destination> {
+		let sessions = self.sessions();
+		let session = sessions.get(service?)?;
+
+		(session.style == style).then(|| session.destination.clone())
+	}
+
+	/// Ends the sessions and stream forwarding owned by a closed connection.
+	fn end_connection(&self, id: u64) {
+		self.control_connections.lock().unwrap().remove(&id);
+		self.versions.lock().unwrap().remove(&id);
+
+		let mut sessions = self.sessions();
+		sessions.retain(|_, session| session.owner != id);
+
+		for session in sessions.values_mut() {
+			if session.stream_forward.is_some_and(|(_, _, owner)| owner == id) {
+				session.stream_forward = None;
+			}
+		}
+	}
+
+	/// Hands out a pending ACCEPT or the forwarding target of a stream session on `destination` and `to_port`,
+	/// preferring sessions listening on `to_port` itself over those listening on any port.
+	fn take_stream(&self, destination: &Destination, to_port: u16) -> Option<Taker> {
+		let mut sessions = self.sessions();
+		let listen_ports = if to_port == 0 { vec![0] } else { vec![to_port, 0] };
+
+		for listen_port in listen_ports {
+			for session in sessions.values_mut() {
+				if session.destination != *destination || session.style != "STREAM" || session.listen_port != listen_port {
+					continue;
+				}
+
+				session.acceptors.retain(|acceptor| !acceptor.is_closed());
+
+				if let Some(acceptor) = session.acceptors.pop_front() {
+					return Some(Taker::Accepting(acceptor));
+				}
+
+				if let Some((address, silent, _)) = session.stream_forward {
+					return Some(Taker::Forwarding(address, silent));
+				}
+			}
+		}
+
+		None
+	}
+
+	/// Resolves a base64 destination, hostname or b32 address.
+	async fn resolve(&self, name: &str) -> Result<Option<NamingLookup>> {
+		match Destination::from_base64(name) {
+			Ok(destination) => Ok(Some(NamingLookup {
+				name: name.to_owned(),
+				destination,
+				options: Default::default(),
+			})),
+			Err(_) => self.transport.look_up(name).await,
+		}
+	}
+
+	async fn resolve_destination(&self, name: &str) -> Result<Option<Destination>> {
+		Ok(self.resolve(name).await?.map(|lookup| lookup.destination))
+	}
+}
+
+fn listens_on(listen_port: u16, to_port: u16) -> bool {
+	listen_port == 0 || listen_port == to_port
+}
+
+async fn accept_connections<T: Transport>(listener: TcpListener, shared: Arc<Shared<T>>) {
+	let mut connections = JoinSet::new();
+
+	loop {
+		tokio::select! {
+			accepted = listener.accept() => {
+				let Ok((connection, _)) = accepted else {
+					return;
+				};
+
+				let id = shared.connection_ids.fetch_add(1, Ordering::Relaxed);
+				let shared = shared.clone();
+
+				connections.spawn(async move {
+					if let Err(error) = serve(BufReader::new(connection), id, &shared).await {
+						debug!("bridge connection {} failed: {}", id, error);
+					}
+
+					shared.end_connection(id);
+				});
+			}
+			Some(_) = connections.join_next(), if !connections.is_empty() => {}
+		}
+	}
+}
+
+/// Answers commands until the connection closes or is taken over by a stream, and writes the datagrams its sessions
+/// receive in between.
+async fn serve<T: Transport>(mut connection: Connection, id: u64, shared: &Arc<Shared<T>>) -> Result<()> {
+	let (received, mut datagrams) = channel(RECEIVED_QUEUE_LENGTH);
+	shared.control_connections.lock().unwrap().insert(id, received);
+
+	// Unlike read_line, read_until keeps what it read if a datagram arrives halfway through a line.
+	let mut line = Vec::new();
+
+	loop {
+		tokio::select! {
+			read = connection.read_until(b'\n', &mut line) => {
+				if read? == 0 {
+					return Ok(());
+				}
+			}
+			Some(datagram) = datagrams.recv() => {
+				connection.get_mut().write_all(&datagram).await?;
+				continue;
+			}
+		}
+
+		let text = String::from_utf8_lossy(&line).into_owned();
+		line.clear();
+
+		if text.trim().is_empty() {
+			continue;
+		}
+
+		// PING carries arbitrary text rather than KEY=VALUE pairs, and gets it back verbatim.
+		if text.trim_end() == "PING" || text.starts_with("PING ") {
+			let pong = format!("PONG{}\n", text["PING".len()..].trim_end());
+			connection.get_mut().write_all(pong.as_bytes()).await?;
+
+			continue;
+		}
+
+		let Ok(command) = SamMessage::parse(&text) else {
+			let topic = text
+				.split_whitespace()
+				.next()
+				.filter(|topic| topic.chars().all(|c| c.is_ascii_alphabetic()))
+				.unwrap_or("SESSION");
+			let malformed = SamMessage::new(topic, reply_subtopic(topic))
+				.with("RESULT", SamResult::I2pError)
+				.with("MESSAGE", "malformed command");
+
+			connection.get_mut().write_all(malformed.serialize().as_bytes()).await?;
+			continue;
+		};
+
+		// DATAGRAM and RAW SEND are followed by their contents, which have to be read even if the command is refused.
+		let contents = if is_send(&command) {
+			let Some(contents) = read_contents(&command, &mut connection).await? else {
+				let invalid = failure_reply(&command, SamResult::I2pError, "invalid SIZE");
+				connection.get_mut().write_all(invalid.serialize().as_bytes()).await?;
+
+				return Ok(());
+			};
+
+			contents
+		} else {
+			Vec::new()
+		};
+
+		let interception = match &shared.interceptor {
+			Some(interceptor) => interceptor.intercept(&command),
+			None => Interception::default(),
+		};
+
+		if let Some(delay) = interception.delay {
+			tokio::time::sleep(delay).await;
+		}
+
+		let next = match interception.failure {
+			Some(result) => reply(connection, failure_reply(&command, result, "refused by the bridge")).await?,
+			None if is_send(&command) => match datagram_send(&command, contents, id, shared).await {
+				Ok(()) => Some(connection),
+				Err(error) => reply(connection, error_reply(&command, &error)).await?,
+			},
+			None => handle(&command, connection, id, shared).await?,
+		};
+
+		match next {
+			Some(next) => connection = next,
+			None => return Ok(()),
+		}
+	}
+}
+
+fn is_send(command: &SamMessage) -> bool {
+	matches!(command.topic.as_str(), "DATAGRAM" | "RAW") && command.subtopic.as_deref() == Some("SEND")
+}
+
+/// Reads the SIZE bytes that follow a DATAGRAM or RAW SEND, or `None` if SIZE is missing or invalid.
+async fn read_contents(command: &SamMessage, connection: &mut Connection) -> Result<Option<Vec<u8>>> {
+	let Some(size) = command.get("SIZE").and_then(|size| size.parse::<u16>().ok()) else {
+		return Ok(None);
+	};
+
+	let mut contents = vec![0u8; size.into()];
+	connection.read_exact(&mut contents).await?;
+
+	Ok(Some(contents))
+}
+
+/// Handles one command, handing the connection back unless it was closed or taken over by a stream.
+async fn handle<T: Transport>(
+	command: &SamMessage,
+	connection: Connection,
+	id: u64,
+	shared: &Arc<Shared<T>>,
+) -> Result<Option<Connection>> {
+	match (command.topic.as_str(), command.subtopic.as_deref()) {
+		("HELLO", Some("VERSION")) => reply(connection, hello(command, id, shared)).await,
+		("DEST", Some("GENERATE")) => reply(connection, dest_generate(command)).await,
+		("SESSION", Some("CREATE")) => reply(connection, session_create(command, id, shared).await).await,
+		("SESSION", Some("ADD")) => reply(connection, session_add(command, id, shared)).await,
+		("SESSION", Some("REMOVE")) => reply(connection, session_remove(command, id, shared)).await,
+		("NAMING", Some("LOOKUP")) => reply(connection, naming_lookup(command, id, shared).await).await,
+		("STREAM", Some("CONNECT")) => stream_connect(command, connection, shared).await,
+		("STREAM", Some("ACCEPT")) => stream_accept(command, connection, id, shared).await,
+		("STREAM", Some("FORWARD")) => stream_forward(command, connection, id, shared).await,
+		("QUIT", _) | ("STOP", _) | ("EXIT", _) => Ok(None),
+		_ => reply(connection, failure_reply(command, SamResult::I2pError, "unsupported command")).await,
+	}
+}
+
+/// Replies, handing the connection back for the next command unless the reply is a refused STREAM command, after
+/// which SAM closes the connection.
+async fn reply(mut connection: Connection, reply: SamMessage) -> Result<Option<Connection>> {
+	connection.get_mut().write_all(reply.serialize().as_bytes()).await?;
+
+	let refused_stream = reply.topic == "STREAM" && reply.get("RESULT") != Some("OK");
+	Ok((!refused_stream).then_some(connection))
+}
+
+fn reply_subtopic(topic: &str) -> &'static str {
+	match topic {
+		"HELLO" | "DEST" | "NAMING" => "REPLY",
+		_ => "STATUS",
+	}
+}
+
+fn ok_reply(command: &SamMessage) -> SamMessage {
+	SamMessage::new(command.topic.as_str(), reply_subtopic(&command.topic)).with("RESULT", "OK")
+}
+
+fn failure_reply(command: &SamMessage, result: SamResult, message: &str) -> SamMessage {
+	SamMessage::new(command.topic.as_str(), reply_subtopic(&command.topic))
+		.with("RESULT", result)
+		.with("MESSAGE", message)
+}
+
+/// Refuses a command because of `error`, keeping the RESULT if it came from a transport.
+fn error_reply(command: &SamMessage, error: &Error) -> SamMessage {
+	match error {
+		Error::Sam { result, message } | Error::Lookup { result, message, .. } => {
+			failure_reply(command, result.clone(), message.as_deref().unwrap_or_default())
+		}
+		error => failure_reply(command, SamResult::I2pError, &error.to_string()),
+	}
+}
+
+fn hello<T: Transport>(command: &SamMessage, id: u64, shared: &Shared<T>) -> SamMessage {
+	let version = |key: &str, default: (u32, u32)| command.get(key).map_or(Some(default), parse_version);
+
+	let (Some(min), Some(max)) = (version("MIN", (3, 0)), version("MAX", VERSION)) else {
+		return failure_reply(command, SamResult::NoVersion, "malformed version");
+	};
+
+	let agreed = max.min(VERSION);
+
+	if agreed < min {
+		return failure_reply(command, SamResult::NoVersion, "no common version");
+	}
+
+	shared.versions.lock().unwrap().insert(id, agreed);
+
+	ok_reply(command).with("VERSION", format!("{}.{}", agreed.0, agreed.1))
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+	let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+
+	Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// The signature type asked for, Ed25519 if none is.
+fn signature_type(command: &SamMessage) -> std::result::Result<SignatureType, SamMessage> {
+	match command.get("SIGNATURE_TYPE").map(str::parse) {
+		Some(Ok(signature_type)) => Ok(signature_type),
+		Some(Err(_)) => Err(failure_reply(command, SamResult::I2pError, "unknown signature type")),
+		None => Ok(SignatureType::EdDsaSha512Ed25519),
+	}
+}
+
+fn dest_generate(command: &SamMessage) -> SamMessage {
+	let keys = match signature_type(command) {
+		Ok(signature_type) => PrivateKeys::generate(signature_type),
+		Err(failure) => return failure,
+	};
+
+	match keys {
+		Ok(keys) => ok_reply(command).with("PUB", keys.destination()).with("PRIV", keys.to_base64()),
+		Err(error) => error_reply(command, &error),
+	}
+}
+
+/// A session as set up by the options of SESSION CREATE or ADD.
+fn new_session(command: &SamMessage, style: &str, destination: Destination, owner: u64) -> std::result::Result<BridgeSession, SamMessage> {
+	let port = |key: &str| -> std::result::Result<Option<u16>, SamMessage> {
+		command
+			.get(key)
+			.map(|port| port.parse())
+			.transpose()
+			.map_err(|_| failure_reply(command, SamResult::I2pError, &format!("invalid {}", key)))
+	};
+
+	let datagram_forward = match port("PORT")? {
+		Some(port) if style == "DATAGRAM" || style == "RAW" => {
+			let host = command.get("HOST").unwrap_or("127.0.0.1");
+
+			match format!("{}:{}", host, port).parse() {
+				Ok(address) => Some(address),
+				Err(_) => return Err(failure_reply(command, SamResult::I2pError, "invalid HOST")),
+			}
+		}
+		_ => None,
+	};
+
+	let protocol = match command.get("PROTOCOL").map(str::parse) {
+		Some(Ok(protocol)) => protocol,
+		Some(Err(_)) => return Err(failure_reply(command, SamResult::I2pError, "invalid PROTOCOL")),
+		None => RAW_PROTOCOL,
+	};
+
+	Ok(BridgeSession {
+		destination,
+		style: style.to_owned(),
+		listen_port: port("LISTEN_PORT")?.unwrap_or(0),
+		owner,
+		datagram_forward,
+		header: command.get("HEADER") == Some("true"),
+		protocol,
+		acceptors: VecDeque::new(),
+		stream_forward: None,
+		endpoint: None,
+	})
+}
+
+async fn session_create<T: Transport>(command: &SamMessage, id: u64, shared: &Arc<Shared<T>>) -> SamMessage {
+	let (Some(service), Some(style)) = (command.get("ID"), command.get("STYLE")) else {
+		return failure_reply(command, SamResult::I2pError, "ID and STYLE are required");
+	};
+
+	let style = match style {
+		"MASTER" => "PRIMARY",
+		"STREAM" | "DATAGRAM" | "RAW" | "PRIMARY" => style,
+		_ => return failure_reply(command, SamResult::I2pError, "unsupported STYLE"),
+	};
+
+	let keys = match command.get("DESTINATION") {
+		Some("TRANSIENT") | None => match signature_type(command) {
+			Ok(signature_type) => PrivateKeys::generate(signature_type),
+			Err(failure) => return failure,
+		},
+		Some(keys) => PrivateKeys::from_base64(keys),
+	};
+
+	let Ok(keys) = keys else {
+		return failure_reply(command, SamResult::InvalidKey, "could not decode DESTINATION");
+	};
+
+	let mut session = match new_session(command, style, keys.destination().clone(), id) {
+		Ok(session) => session,
+		Err(failure) => return failure,
+	};
+
+	if let Some(failure) = check_new_session(command, service, id, shared, Some(keys.destination())) {
+		return failure;
+	}
+
+	let endpoint = match shared.transport.open(&keys).await {
+		Ok(endpoint) => endpoint,
+		Err(error) => return error_reply(command, &error),
+	};
+
+	let mut sessions = shared.sessions();
+
+	// Another connection may have taken the ID while the transport was busy.
+	if sessions.contains_key(service) {
+		return failure_reply(command, SamResult::DuplicatedId, "ID is in use");
+	}
+
+	session.endpoint = Some(Task(tokio::spawn(serve_endpoint(
+		Arc::downgrade(shared),
+		keys.destination().clone(),
+		endpoint,
+	))));
+
+	sessions.insert(service.to_owned(), session);
+
+	debug!("bridge created {} session {}", style, service);
+
+	ok_reply(command).with("DESTINATION", keys.to_base64())
+}
+
+/// Refuses a new session whose ID is taken, whose destination is in use, or whose connection already has a session.
+fn check_new_session<T: Transport>(
+	command: &SamMessage,
+	service: &str,
+	id: u64,
+	shared: &Shared<T>,
+	destination: Option<&Destination>,
+) -> Option<SamMessage> {
+	let sessions = shared.sessions();
+
+	if sessions.contains_key(service) {
+		return Some(failure_reply(command, SamResult::DuplicatedId, "ID is in use"));
+	}
+
+	if let Some(destination) = destination {
+		if sessions.values().any(|session| session.owner == id) {
+			return Some(failure_reply(command, SamResult::I2pError, "this connection already has a session"));
+		}
+
+		if sessions.values().any(|session| session.destination == *destination) {
+			return Some(failure_reply(command, SamResult::DuplicatedDest, "destination is in use"));
+		}
+	}
+
+	None
+}
+
+fn session_add<T: Transport>(command: &SamMessage, id: u64, shared: &Shared<T>) -> SamMessage {
+	let (Some(service), Some(style)) = (command.get("ID"), command.get("STYLE")) else {
+		return failure_reply(command, SamResult::I2pError, "ID and STYLE are required");
+	};
+
+	if !matches!(style, "STREAM" | "DATAGRAM" | "RAW") {
+		return failure_reply(command, SamResult::I2pError, "unsupported STYLE");
+	}
+
+	let primary = shared
+		.sessions()
+		.values()
+		.find(|session| session.owner == id && session.style == "PRIMARY")
+		.map(|session| session.destination.clone());
+
+	let Some(destination) = primary else {
+		return failure_reply(command, SamResult::I2pError, "no primary session on this connection");
+	};
+
+	let session = match new_session(command, style, destination, id) {
+		Ok(session) => session,
+		Err(failure) => return failure,
+	};
+
+	if let Some(failure) = check_new_session(command, service, id, shared, None) {
+		return failure;
+	}
+
+	shared.sessions().insert(service.to_owned(), session);
+
+	debug!("bridge added {} subsession {}", style, service);
+
+	ok_reply(command)
+}
+
+fn session_remove<T: Transport>(command: &SamMessage, id: u64, shared: &Shared<T>) -> SamMessage {
+	let mut sessions = shared.sessions();
+	let service = command.get("ID").unwrap_or_default();
+
+	match sessions.get(service) {
+		Some(session) if session.owner == id && session.endpoint.is_none() => {
+			sessions.remove(service);
+			ok_reply(command)
+		}
+		_ => failure_reply(command, SamResult::InvalidId, "no such subsession"),
+	}
+}
+
+async fn naming_lookup<T: Transport>(command: &SamMessage, id: u64, shared: &Shared<T>) -> SamMessage {
+	let name = command.get("NAME").unwrap_or_default();
+
+	let lookup = if name == "ME" {
+		Ok(shared
+			.sessions()
+			.values()
+			.find(|session| session.owner == id)
+			.map(|session| NamingLookup {
+				name: name.to_owned(),
+				destination: session.destination.clone(),
+				options: Default::default(),
+			}))
+	} else {
+		shared.resolve(name).await
+	};
+
+	match lookup {
+		Ok(Some(mut lookup)) => {
+			if command.get("OPTIONS") != Some("true") {
+				lookup.options.clear();
+			}
+
+			lookup.to_reply()
+		}
+		Ok(None) if is_valid_name(name) => failure_reply(command, SamResult::KeyNotFound, "unknown name").with("NAME", name),
+		Ok(None) => failure_reply(command, SamResult::InvalidKey, "not a hostname or address").with("NAME", name),
+		Err(error) => error_reply(command, &error).with("NAME", name),
+	}
+}
+
+/// Whether `name` could name something: an I2P hostname, or a b32 or b33 address that decodes.
+fn is_valid_name(name: &str) -> bool {
+	if name.ends_with(".b32.i2p") {
+		return name.parse::<B32Address>().is_ok();
+	}
+
+	name.ends_with(".i2p") && name.split('.').all(|label| !label.is_empty())
+}
+
+async fn stream_connect<T: Transport>(command: &SamMessage, mut connection: Connection, shared: &Shared<T>) -> Result<Option<Connection>> {
+	let port = |key: &str| command.get(key).and_then(|port| port.parse().ok()).unwrap_or(0u16);
+
+	let Some(source) = shared.destination_of(command.get("ID"), "STREAM") else {
+		return reply(connection, failure_reply(command, SamResult::InvalidId, "no such stream session")).await;
+	};
+
+	let peer = match shared.resolve_destination(command.get("DESTINATION").unwrap_or_default()).await {
+		Ok(Some(peer)) => peer,
+		Ok(None) => {
+			return reply(
+				connection,
+				failure_reply(command, SamResult::InvalidKey, "could not resolve DESTINATION"),
+			)
+			.await
+		}
+		Err(error) => return reply(connection, error_reply(command, &error)).await,
+	};
+
+	let mut stream = match shared.transport.connect(&source, &peer, port("FROM_PORT"), port("TO_PORT")).await {
+		Ok(stream) => stream,
+		Err(error) => return reply(connection, error_reply(command, &error)).await,
+	};
+
+	connection.get_mut().write_all(ok_reply(command).serialize().as_bytes()).await?;
+
+	// Whatever the client sent after its command is already the stream's first bytes.
+	let buffered = connection.buffer().to_vec();
+	let mut connection = connection.into_inner();
+
+	if stream.write_all(&buffered).await.is_ok() {
+		let _ = tokio::io::copy_bidirectional(&mut connection, &mut stream).await;
+	}
+
+	Ok(None)
+}
+
+async fn stream_accept<T: Transport>(
+	command: &SamMessage,
+	mut connection: Connection,
+	id: u64,
+	shared: &Shared<T>,
+) -> Result<Option<Connection>> {
+	let service = command.get("ID").unwrap_or_default();
+	let version = shared.versions.lock().unwrap().get(&id).copied().unwrap_or((3, 0));
+
+	let refusal = match shared.sessions().get_mut(service) {
+		Some(session) if session.style == "STREAM" => {
+			session.acceptors.retain(|acceptor| !acceptor.is_closed());
+
+			// Before SAM 3.2, a session accepts one stream at a time.
+			(version < (3, 2) && !session.acceptors.is_empty())
+				.then(|| failure_reply(command, SamResult::AlreadyAccepting, "already accepting"))
+		}
+		_ => Some(failure_reply(command, SamResult::InvalidId, "no such stream session")),
+	};
+
+	if let Some(refusal) = refusal {
+		return reply(connection, refusal).await;
+	}
+
+	connection.get_mut().write_all(ok_reply(command).serialize().as_bytes()).await?;
+
+	if let Some(session) = shared.sessions().get_mut(service) {
+		session.acceptors.push_back(Acceptor {
+			stream: connection.into_inner(),
+			silent: command.get("SILENT") == Some("true"),
+		});
+	}
+
+	Ok(None)
+}
+
+async fn stream_forward<T: Transport>(
+	command: &SamMessage,
+	mut connection: Connection,
+	id: u64,
+	shared: &Shared<T>,
+) -> Result<Option<Connection>> {
+	let service = command.get("ID").unwrap_or_default();
+	let host = command.get("HOST").unwrap_or("127.0.0.1");
+
+	let Some(address) = command.get("PORT").and_then(|port| format!("{}:{}", host, port).parse().ok()) else {
+		return reply(connection, failure_reply(command, SamResult::I2pError, "invalid HOST or PORT")).await;
+	};
+
+	let forwarding = match shared.sessions().get_mut(service) {
+		Some(session) if session.style == "STREAM" => {
+			session.stream_forward = Some((address, command.get("SILENT") == Some("true"), id));
+			true
+		}
+		_ => false,
+	};
+
+	if !forwarding {
+		return reply(connection, failure_reply(command, SamResult::InvalidId, "no such stream session")).await;
+	}
+
+	connection.get_mut().write_all(ok_reply(command).serialize().as_bytes()).await?;
+
+	// Forwarding lasts as long as the connection, and is cleaned up once it closes.
+	let mut ignored = Vec::new();
+	while connection.read_until(b'\n', &mut ignored).await.unwrap_or(0) > 0 {
+		ignored.clear();
+	}
+
+	Ok(None)
+}
+
+/// Hands an online destination's incoming streams and datagrams to the sessions on it.
+///
+/// Holds the bridge weakly, since the bridge owns this task.
+async fn serve_endpoint<T: Transport>(shared: Weak<Shared<T>>, destination: Destination, mut endpoint: Endpoint<T::Stream>) {
+	let mut streams = JoinSet::new();
+
+	loop {
+		tokio::select! {
+			Some(incoming) = endpoint.streams.recv() => {
+				streams.spawn(deliver_stream(shared.clone(), destination.clone(), incoming));
+			}
+			Some(datagram) = endpoint.datagrams.recv() => {
+				let Some(shared) = shared.upgrade() else {
+					return;
+				};
+
+				deliver_datagram(&shared, &destination, datagram).await;
+			}
+			Some(_) = streams.join_next(), if !streams.is_empty() => {}
+			else => return,
+		}
+	}
+}
+
+async fn deliver_stream<T: Transport>(shared: Weak<Shared<T>>, destination: Destination, incoming: IncomingStream<T::Stream>) {
+	let waiting_since = tokio::time::Instant::now();
+
+	// An acceptor can hang up between being checked and being handed the stream, so keep going until one takes it.
+	let mut client = loop {
+		let Some(taker) = wait_for_taker(&shared, &destination, incoming.to_port, waiting_since).await else {
+			debug!("bridge dropped a stream to {} that nobody accepted", destination.b32_address());
+			return;
+		};
+
+		let (mut client, silent) = match taker {
+			Taker::Accepting(acceptor) => (acceptor.stream, acceptor.silent),
+			Taker::Forwarding(address, silent) => match TcpStream::connect(address).await {
+				Ok(client) => (client, silent),
+				Err(error) => {
+					debug!("bridge could not forward a stream to {}: {}", address, error);
+					return;
+				}
+			},
+		};
+
+		if !silent {
+			let header = format!("{} FROM_PORT={} TO_PORT={}\n", incoming.peer, incoming.from_port, incoming.to_port);
+
+			if let Err(error) = client.write_all(header.as_bytes()).await {
+				debug!("bridge could not hand a stream to {}: {}", destination.b32_address(), error);
+				continue;
+			}
+		}
+
+		break client;
+	};
+
+	let mut stream = incoming.stream;
+	let _ = tokio::io::copy_bidirectional(&mut client, &mut stream).await;
+}
+
+/// Polls for a session to take a stream until ACCEPT_WAIT has passed since `waiting_since`.
+async fn wait_for_taker<T: Transport>(
+	shared: &Weak<Shared<T>>,
+	destination: &Destination,
+	to_port: u16,
+	waiting_since: tokio::time::Instant,
+) -> Option<Taker> {
+	loop {
+		if let Some(taker) = shared.upgrade()?.take_stream(destination, to_port) {
+			return Some(taker);
+		}
+
+		if waiting_since.elapsed() >= ACCEPT_WAIT {
+			return None;
+		}
+
+		tokio::time::sleep(ACCEPT_POLL_INTERVAL).await;
+	}
+}
+
+async fn deliver_datagram<T: Transport>(shared: &Shared<T>, destination: &Destination, datagram: Datagram) {
+	let style = if datagram.protocol == DATAGRAM_PROTOCOL {
+		"DATAGRAM"
+	} else {
+		"RAW"
+	};
+
+	let deliveries: Vec<(Option<SocketAddr>, u64, Vec<u8>)> = shared
+		.sessions()
+		.values()
+		.filter(|session| {
+			session.destination == *destination && session.style == style && listens_on(session.listen_port, datagram.to_port)
+		})
+		.map(|session| {
+			let header = match (style, session.datagram_forward) {
+				("DATAGRAM", Some(_)) => format!(
+					"{} FROM_PORT={} TO_PORT={}\n",
+					datagram.source, datagram.from_port, datagram.to_port
+				),
+				(_, Some(_)) if session.header => format!(
+					"FROM_PORT={} TO_PORT={} PROTOCOL={}\n",
+					datagram.from_port, datagram.to_port, datagram.protocol
+				),
+				(_, Some(_)) => String::new(),
+				(_, None) => received(style, &datagram).serialize(),
+			};
+
+			let mut bytes = header.into_bytes();
+			bytes.extend_from_slice(&datagram.contents);
+
+			(session.datagram_forward, session.owner, bytes)
+		})
+		.collect();
+
+	for (forward, owner, bytes) in deliveries {
+		match forward {
+			Some(address) => {
+				if let Err(error) = shared.udp.send_to(&bytes, address).await {
+					debug!("bridge could not forward a datagram to {}: {}", address, error);
+				}
+			}
+			None => {
+				let connection = shared.control_connections.lock().unwrap().get(&owner).cloned();
+
+				if connection.is_none_or(|connection| connection.try_send(bytes).is_err()) {
+					debug!("bridge dropped a datagram for connection {}", owner);
+				}
+			}
+		}
+	}
+}
+
+/// The DATAGRAM or RAW RECEIVED line that precedes a datagram on a control connection.
+fn received(style: &str, datagram: &Datagram) -> SamMessage {
+	let mut received = SamMessage::new(style, "RECEIVED");
+
+	if style == "DATAGRAM" {
+		received.push("DESTINATION", &datagram.source);
+	}
+
+	received.push("SIZE", datagram.contents.len());
+	received.push("FROM_PORT", datagram.from_port);
+	received.push("TO_PORT", datagram.to_port);
+
+	if style == "RAW" {
+		received.push("PROTOCOL", datagram.protocol);
+	}
+
+	received
+}
+
+async fn receive_datagrams<T: Transport>(shared: Arc<Shared<T>>) {
+	let mut buffer = vec![0u8; 65536];
+
+	while let Ok((length, _)) = shared.udp.recv_from(&mut buffer).await {
+		if let Err(error) = send_datagram(&shared, &buffer[..length]).await {
+			debug!("bridge dropped a datagram: {}", error);
+		}
+	}
+}
+
+/// Sends a datagram a client handed to the UDP port, which starts with a `3.x ID DESTINATION [options]` line.
+async fn send_datagram<T: Transport>(shared: &Shared<T>, datagram: &[u8]) -> Result<()> {
+	let newline = datagram
+		.iter()
+		.position(|byte| *byte == b'\n')
+		.ok_or_else(|| protocol_error!("datagram has no header"))?;
+
+	let header = std::str::from_utf8(&datagram[..newline]).map_err(|_| protocol_error!("datagram header is not UTF-8"))?;
+
+	let mut words = header.splitn(4, ' ');
+	let (_version, service, peer) = (words.next(), words.next().unwrap_or_default(), words.next().unwrap_or_default());
+
+	let options = SamMessage::parse_pairs(words.next().unwrap_or_default())?;
+
+	send_from(shared, service, peer, &options, datagram[newline + 1..].to_vec()).await
+}
+
+/// Sends a datagram a client handed over its control connection with DATAGRAM or RAW SEND.
+///
+/// It comes from the connection's session of that style, or from the one named by ID.
+async fn datagram_send<T: Transport>(command: &SamMessage, contents: Vec<u8>, id: u64, shared: &Shared<T>) -> Result<()> {
+	let service = match command.get("ID") {
+		Some(service) => service.to_owned(),
+		None => shared
+			.sessions()
+			.iter()
+			.find(|(_, session)| session.owner == id && session.style == command.topic)
+			.map(|(service, _)| service.clone())
+			.ok_or_else(|| protocol_error!("no {} session on this connection", command.topic))?,
+	};
+
+	if shared.destination_of(Some(&service), &command.topic).is_none() {
+		return Err(protocol_error!("no {} session with ID {}", command.topic, service));
+	}
+
+	let peer = command.get("DESTINATION").unwrap_or_default();
+
+	send_from(shared, &service, peer, &command.pairs, contents).await
+}
+
+/// Sends `contents` from session `service` to `peer`, with the ports and protocol in `options`.
+async fn send_from<T: Transport>(
+	shared: &Shared<T>,
+	service: &str,
+	peer: &str,
+	options: &[(String, String)],
+	contents: Vec<u8>,
+) -> Result<()> {
+	let option = |key: &str| options.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str());
+	let port = |key: &str| option(key).map(|value| parse_header_value(key, value)).transpose();
+
+	let (source, style, default_protocol) = {
+		let sessions = shared.sessions();
+		let session = sessions
+			.get(service)
+			.ok_or_else(|| protocol_error!("no session with ID {}", service))?;
+
+		(session.destination.clone(), session.style.clone(), session.protocol)
+	};
+
+	let protocol = match style.as_str() {
+		"DATAGRAM" => DATAGRAM_PROTOCOL,
+		"RAW" => option("PROTOCOL")
+			.map(|value| parse_header_value("PROTOCOL", value))
+			.transpose()?
+			.unwrap_or(default_protocol),
+		_ => return Err(protocol_error!("session {} does not send datagrams", service)),
+	};
+
+	let peer = shared
+		.resolve_destination(peer)
+		.await?
+		.ok_or_else(|| Error::Address(format!("could not resolve {}", peer)))?;
+
+	let datagram = Datagram {
+		source,
+		from_port: port("FROM_PORT")?.unwrap_or(0),
+		to_port: port("TO_PORT")?.unwrap_or(0),
+		protocol,
+		contents,
+	};
+
+	shared.transport.send(&peer, datagram).await
+}
@@ -0,0 +1,153 @@
+use super::{Datagram, Endpoint, IncomingStream, Transport};
+use crate::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex as StdMutex;
+
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc::{channel, Sender};
+
+/// Streams and datagrams waiting for a destination before senders have to wait, or datagrams are dropped.
+const QUEUE_LENGTH: usize = 64;
+
+/// Bytes a loopback stream buffers in each direction.
+const STREAM_BUFFER: usize = 64 * 1024;
+
+/// An in-process network: destinations opened on it reach each other, and nothing else.
+///
+/// Clones share the network, so bridges built on clones of one transport serve destinations that can talk to each
+/// other.
+#[derive(Debug, Clone, Default)]
+pub struct LoopbackTransport {
+	network: Arc<StdMutex<Network>>,
+}
+
+#[derive(Debug, Default)]
+struct Network {
+	inboxes: HashMap<Destination, Inbox>,
+	names: HashMap<String, NamingLookup>,
+}
+
+#[derive(Debug, Clone)]
+struct Inbox {
+	streams: Sender<IncomingStream<DuplexStream>>,
+	datagrams: Sender<Datagram>,
+}
+
+impl LoopbackTransport {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Makes `name` resolve to `destination`, whether or not it is online.
+	pub fn register_name<S: Into<String>>(&self, name: S, destination: Destination) {
+		self.register_name_with_options(name, destination, BTreeMap::new());
+	}
+
+	/// Like [`LoopbackTransport::register_name`], with properties returned to lookups that ask for options.
+	pub fn register_name_with_options<S: Into<String>>(&self, name: S, destination: Destination, options: BTreeMap<String, String>) {
+		let name = name.into();
+
+		self.network().names.insert(
+			name.clone(),
+			NamingLookup {
+				name,
+				destination,
+				options,
+			},
+		);
+	}
+
+	fn network(&self) -> std::sync::MutexGuard<'_, Network> {
+		self.network.lock().unwrap()
+	}
+
+	/// The inbox of `destination`, if it is online.
+	fn inbox(&self, destination: &Destination) -> Option<Inbox> {
+		self.network()
+			.inboxes
+			.get(destination)
+			.filter(|inbox| !inbox.streams.is_closed())
+			.cloned()
+	}
+}
+
+fn unreachable_peer() -> Error {
+	Error::Sam {
+		result: SamResult::CantReachPeer,
+		message: Some("destination is offline".to_owned()),
+	}
+}
+
+impl Transport for LoopbackTransport {
+	type Stream = DuplexStream;
+
+	async fn open(&self, keys: &PrivateKeys) -> Result<Endpoint<DuplexStream>> {
+		if self.inbox(keys.destination()).is_some() {
+			return Err(Error::Sam {
+				result: SamResult::DuplicatedDest,
+				message: Some("destination is already online".to_owned()),
+			});
+		}
+
+		let (streams, stream_receiver) = channel(QUEUE_LENGTH);
+		let (datagrams, datagram_receiver) = channel(QUEUE_LENGTH);
+
+		self.network()
+			.inboxes
+			.insert(keys.destination().clone(), Inbox { streams, datagrams });
+
+		Ok(Endpoint {
+			streams: stream_receiver,
+			datagrams: datagram_receiver,
+		})
+	}
+
+	async fn connect(&self, source: &Destination, peer: &Destination, from_port: u16, to_port: u16) -> Result<DuplexStream> {
+		let inbox = self.inbox(peer).ok_or_else(unreachable_peer)?;
+		let (local, remote) = tokio::io::duplex(STREAM_BUFFER);
+
+		let incoming = IncomingStream {
+			stream: remote,
+			peer: source.clone(),
+			from_port,
+			to_port,
+		};
+
+		inbox.streams.send(incoming).await.map_err(|_| unreachable_peer())?;
+
+		Ok(local)
+	}
+
+	async fn send(&self, peer: &Destination, datagram: Datagram) -> Result<()> {
+		if let Some(inbox) = self.inbox(peer) {
+			let _ = inbox.datagrams.try_send(datagram);
+		}
+
+		Ok(())
+	}
+
+	async fn look_up(&self, name: &str) -> Result<Option<NamingLookup>> {
+		let network = self.network();
+
+		if let Some(lookup) = network.names.get(name) {
+			return Ok(Some(lookup.clone()));
+		}
+
+		let Ok(address) = name.parse::<B32Address>() else {
+			return Ok(None);
+		};
+
+		let destination = network
+			.inboxes
+			.keys()
+			.chain(network.names.values().map(|lookup| &lookup.destination))
+			.find(|destination| address.matches(destination));
+
+		Ok(destination.map(|destination| NamingLookup {
+			name: name.to_owned(),
+			destination: destination.clone(),
+			options: BTreeMap::new(),
+		}))
+	}
+}
@@ -0,0 +1,229 @@
+use crate::*;
+
+/// Address of the SAM bridge's TCP port on a stock i2p or i2pd install.
+pub const DEFAULT_SAM_ADDRESS: &str = "localhost:7656";
+
+/// Address of the SAM bridge's UDP port on a stock i2p or i2pd install.
+pub const DEFAULT_DATAGRAM_ADDRESS: &str = "127.0.0.1:7655";
+
+/// Environment variable overriding [`SamConfig::sam_address`].
+pub const SAM_ADDRESS_VARIABLE: &str = "I2P_SAM_ADDRESS";
+
+/// Environment variable overriding [`SamConfig::datagram_address`].
+pub const DATAGRAM_ADDRESS_VARIABLE: &str = "I2P_SAM_DATAGRAM_ADDRESS";
+
+/// Where the SAM bridge lives and how to talk to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamConfig {
+	/// `host:port` of the bridge's TCP port.
+	pub sam_address: String,
+	/// `host:port` of the bridge's UDP port, used for datagram and raw sessions.
+	pub datagram_address: String,
+	/// Lowest SAM version offered in HELLO.
+	pub min_version: String,
+	/// Highest SAM version offered in HELLO.
+	pub max_version: String,
+	pub timeouts: Timeouts,
+}
+
+impl SamConfig {
+	/// Default configuration, with the bridge addresses overridden by `I2P_SAM_ADDRESS` and
+	/// `I2P_SAM_DATAGRAM_ADDRESS` when they are set.
+	pub fn from_env() -> Self {
+		Self::from_variables(|name| std::env::var(name).ok())
+	}
+
+	/// Like [`SamConfig::from_env`], reading the variables with `variable` instead of from the environment.
+	pub fn from_variables(variable: impl Fn(&str) -> Option<String>) -> Self {
+		let mut config = Self::default();
+
+		if let Some(address) = variable(SAM_ADDRESS_VARIABLE) {
+			config.sam_address = address;
+		}
+
+		if let Some(address) = variable(DATAGRAM_ADDRESS_VARIABLE) {
+			config.datagram_address = address;
+		}
+
+		config
+	}
+}
+
+impl Default for SamConfig {
+	fn default() -> Self {
+		Self {
+			sam_address: DEFAULT_SAM_ADDRESS.to_owned(),
+			datagram_address: DEFAULT_DATAGRAM_ADDRESS.to_owned(),
+			min_version: "3.0".to_owned(),
+			max_version: "3.3".to_owned(),
+			timeouts: Timeouts::default(),
+		}
+	}
+}
+
+/// How long to wait for each kind of exchange with the bridge before giving up with [`Error::Timeout`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeouts {
+	/// Opening the TCP connection to the bridge.
+	pub connect: Duration,
+	pub hello: Duration,
+	pub dest_generate: Duration,
+	/// Creating a session, which waits for the first tunnels to be built and can take minutes.
+	pub session_create: Duration,
+	/// Connecting a stream, which includes looking up the peer's leaseset.
+	pub stream_connect: Duration,
+	pub naming_lookup: Duration,
+	/// Every other command, such as STREAM FORWARD.
+	pub other: Duration,
+}
+
+impl Timeouts {
+	pub fn for_operation(&self, operation: Operation) -> Duration {
+		match operation {
+			Operation::Connect => self.connect,
+			Operation::Hello => self.hello,
+			Operation::DestGenerate => self.dest_generate,
+			Operation::SessionCreate => self.session_create,
+			Operation::StreamConnect => self.stream_connect,
+			Operation::NamingLookup => self.naming_lookup,
+			Operation::Other => self.other,
+		}
+	}
+}
+
+impl Default for Timeouts {
+	fn default() -> Self {
+		Self {
+			connect: Duration::from_secs(30),
+			hello: Duration::from_secs(30),
+			dest_generate: Duration::from_secs(60),
+			session_create: Duration::from_secs(300),
+			stream_connect: Duration::from_secs(180),
+			naming_lookup: Duration::from_secs(90),
+			other: Duration::from_secs(60),
+		}
+	}
+}
+
+/// Configures and opens a [`Session`].
+///
+/// Starts from [`SamConfig::from_env`], so deployments can point at a different bridge without code changes.
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+	service: String,
+	session_style: SessionStyle,
+	keys: Option<PrivateKeys>,
+	signature_type: Option<SignatureType>,
+	options: SessionOptions,
+	config: SamConfig,
+}
+
+impl SessionBuilder {
+	pub fn new<S: Into<String>>(service: S, session_style: SessionStyle) -> Self {
+		Self {
+			service: service.into(),
+			session_style,
+			keys: None,
+			signature_type: None,
+			options: SessionOptions::default(),
+			config: SamConfig::from_env(),
+		}
+	}
+
+	/// Restores an existing identity instead of generating a new one.
+	pub fn keys(mut self, private_keys: PrivateKeys) -> Self {
+		self.keys = Some(private_keys);
+		self
+	}
+
+	/// Requires the session's destination to sign with `signature_type`.
+	///
+	/// New destinations are generated with it; restored keys of another type are rejected by [`SessionBuilder::build`].
+	pub fn signature_type(mut self, signature_type: SignatureType) -> Self {
+		self.signature_type = Some(signature_type);
+		self
+	}
+
+	/// Publishes the session's leaseset encrypted, so it is only reachable through its b33 address.
+	pub fn encrypted_lease_set(mut self, encrypted_lease_set: EncryptedLeaseSet) -> Self {
+		self.options.encrypted_lease_set = Some(encrypted_lease_set);
+		self
+	}
+
+	/// Replaces the I2CP and streaming options sent with SESSION CREATE.
+	pub fn options(mut self, options: SessionOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	/// Adds one I2CP or streaming option by name.
+	pub fn option<K: Into<String>, V: ToString>(mut self, key: K, value: V) -> Self {
+		self.options.extra.insert(key.into(), value.to_string());
+		self
+	}
+
+	/// Replaces the whole bridge configuration.
+	pub fn config(mut self, config: SamConfig) -> Self {
+		self.config = config;
+		self
+	}
+
+	pub fn sam_address<S: Into<String>>(mut self, address: S) -> Self {
+		self.config.sam_address = address.into();
+		self
+	}
+
+	pub fn datagram_address<S: Into<String>>(mut self, address: S) -> Self {
+		self.config.datagram_address = address.into();
+		self
+	}
+
+	/// Sets the range of SAM versions offered in HELLO.
+	pub fn version<S: Into<String>>(mut self, min_version: S, max_version: S) -> Self {
+		self.config.min_version = min_version.into();
+		self.config.max_version = max_version.into();
+		self
+	}
+
+	pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+		self.config.timeouts = timeouts;
+		self
+	}
+
+	pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+		self.config.timeouts.connect = timeout;
+		self
+	}
+
+	/// Connects to the bridge, says HELLO and, unless keys were supplied, generates a new destination.
+	pub async fn build(self) -> Result<Session> {
+		self.check_keys()?;
+
+		let mut session = Session::connect(self.service, self.session_style, self.config, self.keys, self.signature_type).await?;
+		session.options = self.options;
+
+		Ok(session)
+	}
+
+	/// Like [`SessionBuilder::build`], but creates a SAM 3.3 PRIMARY session, which subsessions of every style can be
+	/// added to. The builder's session style is not used.
+	pub async fn build_primary(self) -> Result<PrimarySession> {
+		self.check_keys()?;
+
+		PrimarySession::create(self.service, self.config, self.keys, self.signature_type, self.options).await
+	}
+
+	fn check_keys(&self) -> Result<()> {
+		if let (Some(keys), Some(signature_type)) = (&self.keys, self.signature_type) {
+			if keys.destination().signature_type() != signature_type {
+				return Err(Error::Address(format!(
+					"destination signs with {}, not {}",
+					keys.destination().signature_type(),
+					signature_type
+				)));
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,151 @@
+use crate::*;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// A TCP connection to the SAM bridge that has completed HELLO.
+///
+/// Sessions keep one of these as their control socket, and open more for every stream they accept or connect.
+#[derive(Debug)]
+pub(crate) struct SamConnection {
+	stream: BufReader<TcpStream>,
+	config: SamConfig,
+	/// Label used in log messages, usually the session ID.
+	label: String,
+	version: String,
+	/// Replies still owed by the bridge. SAM answers in order, so when a command times out or is cancelled, its reply
+	/// is read and dropped before the next one.
+	pending: usize,
+	/// The part of a reply line read so far, kept here so that a cancelled read doesn't lose it.
+	line: Vec<u8>,
+	/// Set while a command is being written. If it is still set when the next command starts, the bridge got half a
+	/// command and there is no telling what it will answer.
+	torn: bool,
+}
+
+impl SamConnection {
+	pub(crate) async fn connect(label: &str, config: &SamConfig) -> Result<Self> {
+		trace!("sam connection with ID {} is connecting to {}", label, config.sam_address);
+
+		let stream = tokio::time::timeout(config.timeouts.connect, TcpStream::connect(&config.sam_address))
+			.await
+			.map_err(|_| Error::Timeout(Operation::Connect))??;
+
+		let mut connection = Self {
+			stream: BufReader::new(stream),
+			config: config.clone(),
+			label: label.to_owned(),
+			version: String::new(),
+			pending: 0,
+			line: Vec::new(),
+			torn: false,
+		};
+
+		connection.hello().await?;
+
+		Ok(connection)
+	}
+
+	/// Whether the version agreed on in HELLO is at least `major.minor`.
+	pub(crate) fn supports(&self, major: u32, minor: u32) -> bool {
+		let mut parts = self.version.split('.').map(|part| part.parse::<u32>().unwrap_or(0));
+		let version = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+
+		version >= (major, minor)
+	}
+
+	async fn hello(&mut self) -> Result<()> {
+		debug!("sam connection with ID {} is executing hello", self.label);
+
+		let hello = SamMessage::new("HELLO", "VERSION")
+			.with("MIN", &self.config.min_version)
+			.with("MAX", &self.config.max_version);
+
+		let reply = self.command(&hello).await?;
+		self.version = reply.require("VERSION")?.to_owned();
+
+		debug!("sam connection with ID {} negotiated version {}", self.label, self.version);
+
+		Ok(())
+	}
+
+	/// Sends a command and waits for its reply, failing if the reply carries a RESULT other than OK.
+	pub(crate) async fn command(&mut self, command: &SamMessage) -> Result<SamMessage> {
+		debug!("sam connection with ID {} is executing command {}", self.label, command);
+
+		let operation = Operation::of(command);
+		let timeout = self.config.timeouts.for_operation(operation);
+
+		let response = tokio::time::timeout(timeout, self.exchange(command))
+			.await
+			.map_err(|_| Error::Timeout(operation))??;
+
+		trace!(
+			"sam connection with ID {} sent command {} and got response {}",
+			self.label,
+			command,
+			response
+		);
+
+		let reply = SamMessage::parse(&response)?;
+
+		if reply.topic != command.topic {
+			return Err(protocol_error!("expected a {} reply from SAM, got: {}", command.topic, reply));
+		}
+
+		match reply.get("RESULT") {
+			Some("OK") | None => Ok(reply),
+			Some(result) => Err(Error::Sam {
+				result: SamResult::from_code(result),
+				message: reply.get("MESSAGE").map(str::to_owned),
+			}),
+		}
+	}
+
+	async fn exchange(&mut self, command: &SamMessage) -> Result<String> {
+		if self.torn {
+			let _ = self.stream.shutdown().await;
+			return Err(Error::Interrupted);
+		}
+
+		while self.pending > 0 {
+			let late = self.read_reply().await?;
+			self.pending -= 1;
+			debug!("sam connection with ID {} dropped a late reply: {}", self.label, late.trim_end());
+		}
+
+		self.torn = true;
+		self.stream.write_all(command.serialize().as_bytes()).await?;
+		self.stream.flush().await?;
+		self.torn = false;
+		self.pending += 1;
+
+		trace!("reading from SAM socket");
+		let response = self.read_reply().await?;
+		self.pending -= 1;
+		trace!("read from SAM socket");
+
+		Ok(response)
+	}
+
+	async fn read_reply(&mut self) -> Result<String> {
+		self.stream.read_until(b'\n', &mut self.line).await?;
+
+		if self.line.last() != Some(&b'\n') {
+			return Err(protocol_error!("SAM bridge closed the connection"));
+		}
+
+		String::from_utf8(std::mem::take(&mut self.line)).map_err(|_| protocol_error!("SAM bridge sent a reply that isn't UTF-8"))
+	}
+
+	/// Gives up the connection for use as a data socket, after STREAM CONNECT or STREAM ACCEPT.
+	pub(crate) fn into_stream(self) -> BufReader<TcpStream> {
+		self.stream
+	}
+
+	pub(crate) async fn shutdown(&mut self) -> Result<()> {
+		self.stream.shutdown().await?;
+
+		Ok(())
+	}
+}
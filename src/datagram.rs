@@ -1,10 +1,27 @@
 use crate::*;
 
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::net::UdpSocket;
+
+/// A repliable datagram, as written to and read from the bridge's UDP port.
+///
+/// The optional fields are SAM 3.2 options. When any of them is set the datagram is sent with a `3.2` header
+/// carrying them; incoming datagrams fill in whichever ones the bridge reported, usually FROM_PORT and TO_PORT.
 #[derive(Debug, PartialEq)]
 pub struct DatagramMessage {
 	pub service: String,
 	pub destination: String,
 	pub contents: Vec<u8>,
+	pub from_port: Option<u16>,
+	pub to_port: Option<u16>,
+	/// I2CP protocol number, only meaningful for raw datagrams.
+	pub protocol: Option<u8>,
+	pub send_tags: Option<u32>,
+	pub tag_threshold: Option<u32>,
+	/// Lifetime of the datagram, in seconds.
+	pub expires: Option<u32>,
+	pub send_leaseset: Option<bool>,
 }
 
 impl DatagramMessage {
@@ -13,13 +30,51 @@ impl DatagramMessage {
 			service: service.into(),
 			destination: destination.into(),
 			contents,
+			from_port: None,
+			to_port: None,
+			protocol: None,
+			send_tags: None,
+			tag_threshold: None,
+			expires: None,
+			send_leaseset: None,
 		}
 	}
 
+	/// The SAM 3.2 options that are set, in the order they are written.
+	fn options(&self) -> Vec<(&'static str, String)> {
+		let options = [
+			("FROM_PORT", self.from_port.map(|port| port.to_string())),
+			("TO_PORT", self.to_port.map(|port| port.to_string())),
+			("PROTOCOL", self.protocol.map(|protocol| protocol.to_string())),
+			("SEND_TAGS", self.send_tags.map(|tags| tags.to_string())),
+			("TAG_THRESHOLD", self.tag_threshold.map(|threshold| threshold.to_string())),
+			("EXPIRES", self.expires.map(|expires| expires.to_string())),
+			("SEND_LEASESET", self.send_leaseset.map(|send| send.to_string())),
+		];
+
+		options
+			.into_iter()
+			.filter_map(|(key, value)| value.map(|value| (key, value)))
+			.collect()
+	}
+
 	pub fn serialize(&self) -> Vec<u8> {
 		debug!("serializing datagram message");
 
-		let header = format!("3.0 {} {}\n", self.service, self.destination);
+		let options = self.options();
+
+		let mut header = if options.is_empty() {
+			format!("3.0 {} {}", self.service, self.destination)
+		} else {
+			format!("3.2 {} {}", self.service, self.destination)
+		};
+
+		for (key, value) in options {
+			header.push_str(&format!(" {}={}", key, value));
+		}
+
+		header.push('\n');
+
 		let mut bytes = header.as_bytes().to_vec();
 		bytes.append(&mut self.contents.clone());
 
@@ -32,26 +87,178 @@ impl DatagramMessage {
 		// Split the buffer, using the first 0x0a (newline) byte as the delimiter
 		let split_buffer: Vec<&[u8]> = buffer.splitn(2, |byte| *byte == 0x0a).collect();
 
-		let header_bytes = split_buffer.get(0).context("Cannot deserialize an empty buffer")?;
+		let header_bytes = split_buffer
+			.first()
+			.ok_or_else(|| protocol_error!("Cannot deserialize an empty buffer"))?;
 
 		let header = String::from_utf8(header_bytes.to_vec())?;
+		let (destination, options) = header.split_once(' ').unwrap_or((&header, ""));
 
-		let expression = regex::Regex::new("^[^ ]+")?;
+		if destination.is_empty() {
+			return Err(protocol_error!("Could not find destination in header"));
+		}
 
-		let destination = expression
-			.captures(&header)
-			.context("Could not regex header")?
-			.get(0)
-			.context("Could not find destination in header")?
-			.as_str()
-			.to_owned();
+		let contents = split_buffer
+			.get(1)
+			.ok_or_else(|| protocol_error!("could not find contents of datagram message"))?
+			.to_vec();
 
-		let contents = split_buffer.get(1).context("could not find contents of datagram message")?.to_vec();
+		let mut message = Self::new(service.into(), destination.to_owned(), contents);
 
-		Ok(Self {
-			service: service.into(),
-			destination,
-			contents,
-		})
+		for (key, value) in SamMessage::parse_pairs(options)? {
+			match key.as_str() {
+				"FROM_PORT" => message.from_port = Some(parse_header_value(&key, &value)?),
+				"TO_PORT" => message.to_port = Some(parse_header_value(&key, &value)?),
+				"PROTOCOL" => message.protocol = Some(parse_header_value(&key, &value)?),
+				"SEND_TAGS" => message.send_tags = Some(parse_header_value(&key, &value)?),
+				"TAG_THRESHOLD" => message.tag_threshold = Some(parse_header_value(&key, &value)?),
+				"EXPIRES" => message.expires = Some(parse_header_value(&key, &value)?),
+				"SEND_LEASESET" => message.send_leaseset = Some(parse_header_value(&key, &value)?),
+				_ => trace!("ignoring datagram header {}={}", key, value),
+			}
+		}
+
+		Ok(message)
+	}
+}
+
+/// Parses the value of a datagram header option.
+pub(crate) fn parse_header_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+	value
+		.parse()
+		.map_err(|_| protocol_error!("invalid {} in datagram header: {}", key, value))
+}
+
+/// A UDP-like socket for sending and receiving repliable datagrams over I2P.
+///
+/// Owns a datagram session, and the local UDP socket the bridge forwards that session's datagrams to.
+#[derive(Debug)]
+pub struct I2pDatagramSocket {
+	socket: ForwardedSocket,
+	session: Session,
+}
+
+impl I2pDatagramSocket {
+	/// Creates a datagram session with a new destination and binds a socket to it.
+	pub async fn bind<S: Into<String>>(service: S) -> Result<Self> {
+		Self::from_session(Session::new(service, SessionStyle::Datagram).await?).await
+	}
+
+	/// Binds a socket to a datagram session that has not been forwarded yet.
+	pub async fn from_session(mut session: Session) -> Result<Self> {
+		if session.session_style != SessionStyle::Datagram {
+			return Err(protocol_error!("datagram sockets need a datagram session"));
+		}
+
+		let socket = ForwardedSocket::bind(&mut session, false).await?;
+
+		Ok(Self { socket, session })
+	}
+
+	/// Sends `buffer` as one datagram to `destination`, returning the number of payload bytes sent.
+	pub async fn send_to(&self, buffer: &[u8], destination: &Destination) -> Result<usize> {
+		let datagram = DatagramMessage::new(self.session.service.clone(), destination.to_base64(), buffer.to_vec());
+
+		self.socket.send(&datagram.serialize()).await?;
+
+		Ok(buffer.len())
+	}
+
+	/// Sends a datagram with its SAM 3.2 options, such as ports; its service is replaced with this socket's.
+	pub async fn send(&self, mut datagram: DatagramMessage) -> Result<usize> {
+		datagram.service = self.session.service.clone();
+
+		self.socket.send(&datagram.serialize()).await?;
+
+		Ok(datagram.contents.len())
+	}
+
+	/// Waits for the next datagram, returning its payload and the destination that sent it.
+	pub async fn recv_from(&self) -> Result<(Vec<u8>, Destination)> {
+		let datagram = self.recv().await?;
+		let destination = Destination::from_base64(&datagram.destination)?;
+
+		Ok((datagram.contents, destination))
+	}
+
+	/// Waits for the next datagram, including the ports the bridge reported for it.
+	pub async fn recv(&self) -> Result<DatagramMessage> {
+		let buffer = self.socket.recv().await?;
+
+		DatagramMessage::from_bytes(self.session.service.as_str(), &buffer)
+	}
+
+	/// The destination other peers send datagrams to.
+	pub fn local_destination(&self) -> &Destination {
+		&self.session.public_key
+	}
+
+	pub fn session(&self) -> &Session {
+		&self.session
+	}
+}
+
+/// Largest datagram expected from the bridge: a full I2P datagram plus its header line.
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+/// The local UDP socket a datagram or raw session is forwarded to.
+#[derive(Debug)]
+pub(crate) struct ForwardedSocket {
+	socket: UdpSocket,
+	bridge_address: SocketAddr,
+}
+
+impl ForwardedSocket {
+	/// Binds a UDP socket on the interface that reaches the bridge's datagram port, and forwards the session to it.
+	pub(crate) async fn bind(session: &mut Session, header: bool) -> Result<Self> {
+		let config = session.config();
+
+		let bridge_address = tokio::net::lookup_host(&config.datagram_address)
+			.await?
+			.next()
+			.ok_or_else(|| protocol_error!("could not resolve {}", config.datagram_address))?;
+
+		let unspecified: SocketAddr = match bridge_address {
+			SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+			SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+		};
+
+		// Connecting a throwaway socket makes the OS pick the interface that routes to the bridge.
+		let probe = UdpSocket::bind(unspecified).await?;
+		probe.connect(bridge_address).await?;
+		let local_ip = probe.local_addr()?.ip();
+
+		let socket = UdpSocket::bind((local_ip, 0)).await?;
+		let local_address = socket.local_addr()?;
+
+		session
+			.forward_datagrams(local_address.ip().to_string(), local_address.port(), header)
+			.await?;
+
+		Ok(Self { socket, bridge_address })
+	}
+
+	pub(crate) async fn send(&self, buffer: &[u8]) -> Result<()> {
+		self.socket.send_to(buffer, self.bridge_address).await?;
+
+		Ok(())
+	}
+
+	/// Waits for the next datagram from the bridge, ignoring anything sent from elsewhere.
+	pub(crate) async fn recv(&self) -> Result<Vec<u8>> {
+		let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+
+		loop {
+			let (length, sender) = self.socket.recv_from(&mut buffer).await?;
+
+			if sender.ip() != self.bridge_address.ip() {
+				debug!("ignoring datagram from {}, which is not the SAM bridge", sender);
+				continue;
+			}
+
+			buffer.truncate(length);
+
+			return Ok(buffer);
+		}
 	}
 }
@@ -0,0 +1,563 @@
+use crate::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+use data_encoding::{Encoding, Specification};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Size of the encryption public key field at the start of a destination.
+const PUBLIC_KEY_FIELD_LENGTH: usize = 256;
+
+/// Size of the signing public key field that follows it.
+const SIGNING_KEY_FIELD_LENGTH: usize = 128;
+
+const NULL_CERTIFICATE: u8 = 0;
+const KEY_CERTIFICATE: u8 = 5;
+
+const SIGNATURE_TYPES: [SignatureType; 10] = [
+	SignatureType::DsaSha1,
+	SignatureType::EcdsaSha256P256,
+	SignatureType::EcdsaSha384P384,
+	SignatureType::EcdsaSha512P521,
+	SignatureType::RsaSha256_2048,
+	SignatureType::RsaSha384_3072,
+	SignatureType::RsaSha512_4096,
+	SignatureType::EdDsaSha512Ed25519,
+	SignatureType::EdDsaSha512Ed25519ph,
+	SignatureType::RedDsaSha512Ed25519,
+];
+
+/// Signature algorithms a destination can sign with, numbered as in the I2P common structures spec.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SignatureType {
+	DsaSha1,
+	EcdsaSha256P256,
+	EcdsaSha384P384,
+	EcdsaSha512P521,
+	RsaSha256_2048,
+	RsaSha384_3072,
+	RsaSha512_4096,
+	EdDsaSha512Ed25519,
+	EdDsaSha512Ed25519ph,
+	RedDsaSha512Ed25519,
+}
+
+impl SignatureType {
+	pub fn from_code(code: u16) -> Option<Self> {
+		Some(match code {
+			0 => Self::DsaSha1,
+			1 => Self::EcdsaSha256P256,
+			2 => Self::EcdsaSha384P384,
+			3 => Self::EcdsaSha512P521,
+			4 => Self::RsaSha256_2048,
+			5 => Self::RsaSha384_3072,
+			6 => Self::RsaSha512_4096,
+			7 => Self::EdDsaSha512Ed25519,
+			8 => Self::EdDsaSha512Ed25519ph,
+			11 => Self::RedDsaSha512Ed25519,
+			_ => return None,
+		})
+	}
+
+	pub fn code(&self) -> u16 {
+		match self {
+			Self::DsaSha1 => 0,
+			Self::EcdsaSha256P256 => 1,
+			Self::EcdsaSha384P384 => 2,
+			Self::EcdsaSha512P521 => 3,
+			Self::RsaSha256_2048 => 4,
+			Self::RsaSha384_3072 => 5,
+			Self::RsaSha512_4096 => 6,
+			Self::EdDsaSha512Ed25519 => 7,
+			Self::EdDsaSha512Ed25519ph => 8,
+			Self::RedDsaSha512Ed25519 => 11,
+		}
+	}
+
+	/// The name SAM uses for the signature type in SIGNATURE_TYPE.
+	pub fn as_string(&self) -> &str {
+		match self {
+			Self::DsaSha1 => "DSA_SHA1",
+			Self::EcdsaSha256P256 => "ECDSA_SHA256_P256",
+			Self::EcdsaSha384P384 => "ECDSA_SHA384_P384",
+			Self::EcdsaSha512P521 => "ECDSA_SHA512_P521",
+			Self::RsaSha256_2048 => "RSA_SHA256_2048",
+			Self::RsaSha384_3072 => "RSA_SHA384_3072",
+			Self::RsaSha512_4096 => "RSA_SHA512_4096",
+			Self::EdDsaSha512Ed25519 => "EdDSA_SHA512_Ed25519",
+			Self::EdDsaSha512Ed25519ph => "EdDSA_SHA512_Ed25519ph",
+			Self::RedDsaSha512Ed25519 => "RedDSA_SHA512_Ed25519",
+		}
+	}
+
+	pub fn public_key_length(&self) -> usize {
+		match self {
+			Self::DsaSha1 => 128,
+			Self::EcdsaSha256P256 => 64,
+			Self::EcdsaSha384P384 => 96,
+			Self::EcdsaSha512P521 => 132,
+			Self::RsaSha256_2048 => 256,
+			Self::RsaSha384_3072 => 384,
+			Self::RsaSha512_4096 => 512,
+			Self::EdDsaSha512Ed25519 | Self::EdDsaSha512Ed25519ph | Self::RedDsaSha512Ed25519 => 32,
+		}
+	}
+
+	pub fn private_key_length(&self) -> usize {
+		match self {
+			Self::DsaSha1 => 20,
+			Self::EcdsaSha256P256 => 32,
+			Self::EcdsaSha384P384 => 48,
+			Self::EcdsaSha512P521 => 66,
+			Self::RsaSha256_2048 => 512,
+			Self::RsaSha384_3072 => 768,
+			Self::RsaSha512_4096 => 1024,
+			Self::EdDsaSha512Ed25519 | Self::EdDsaSha512Ed25519ph | Self::RedDsaSha512Ed25519 => 32,
+		}
+	}
+}
+
+impl FromStr for SignatureType {
+	type Err = Error;
+
+	/// Parses a SAM signature type name, case-insensitively, or its numeric code.
+	fn from_str(name: &str) -> Result<Self> {
+		SIGNATURE_TYPES
+			.into_iter()
+			.find(|signature_type| signature_type.as_string().eq_ignore_ascii_case(name))
+			.or_else(|| name.parse().ok().and_then(Self::from_code))
+			.ok_or_else(|| Error::Address(format!("unknown signature type {}", name)))
+	}
+}
+
+impl fmt::Display for SignatureType {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		formatter.write_str(self.as_string())
+	}
+}
+
+/// Encryption algorithms a destination's public key can be for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EncryptionType {
+	ElGamal,
+	EcP256,
+	EcP384,
+	EcP521,
+	X25519,
+}
+
+impl EncryptionType {
+	pub fn from_code(code: u16) -> Option<Self> {
+		Some(match code {
+			0 => Self::ElGamal,
+			1 => Self::EcP256,
+			2 => Self::EcP384,
+			3 => Self::EcP521,
+			4 => Self::X25519,
+			_ => return None,
+		})
+	}
+
+	pub fn code(&self) -> u16 {
+		match self {
+			Self::ElGamal => 0,
+			Self::EcP256 => 1,
+			Self::EcP384 => 2,
+			Self::EcP521 => 3,
+			Self::X25519 => 4,
+		}
+	}
+
+	pub fn public_key_length(&self) -> usize {
+		match self {
+			Self::ElGamal => 256,
+			Self::EcP256 => 64,
+			Self::EcP384 => 96,
+			Self::EcP521 => 132,
+			Self::X25519 => 32,
+		}
+	}
+
+	pub fn private_key_length(&self) -> usize {
+		match self {
+			Self::ElGamal => 256,
+			Self::EcP256 => 32,
+			Self::EcP384 => 48,
+			Self::EcP521 => 66,
+			Self::X25519 => 32,
+		}
+	}
+}
+
+/// The certificate closing a destination.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Certificate {
+	pub certificate_type: u8,
+	pub payload: Vec<u8>,
+}
+
+/// The public half of an I2P identity: encryption key, signing key and certificate.
+///
+/// Destinations compare equal when their encodings are byte-for-byte equal, padding included.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Destination {
+	bytes: Vec<u8>,
+	signature_type: SignatureType,
+	encryption_type: EncryptionType,
+}
+
+impl Destination {
+	/// Decodes a destination from I2P's base64, as used in SAM commands.
+	pub fn from_base64(base64: &str) -> Result<Self> {
+		Self::from_bytes(&decode_base64(base64)?)
+	}
+
+	/// Decodes a destination from its binary form, which must not have anything after it.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+		let (destination, length) = Self::read(bytes)?;
+
+		if length != bytes.len() {
+			return Err(Error::Address(format!(
+				"{} unexpected bytes after destination",
+				bytes.len() - length
+			)));
+		}
+
+		Ok(destination)
+	}
+
+	/// Decodes the destination at the start of `bytes`, returning it with the number of bytes it took.
+	pub(crate) fn read(bytes: &[u8]) -> Result<(Self, usize)> {
+		let header_length = PUBLIC_KEY_FIELD_LENGTH + SIGNING_KEY_FIELD_LENGTH;
+
+		if bytes.len() < header_length + 3 {
+			return Err(Error::Address(format!("destination is too short: {} bytes", bytes.len())));
+		}
+
+		let certificate_type = bytes[header_length];
+		let payload_length = u16::from_be_bytes([bytes[header_length + 1], bytes[header_length + 2]]) as usize;
+		let length = header_length + 3 + payload_length;
+
+		if bytes.len() < length {
+			return Err(Error::Address("destination certificate is truncated".to_owned()));
+		}
+
+		let payload = &bytes[header_length + 3..length];
+
+		let (signature_type, encryption_type) = match certificate_type {
+			NULL_CERTIFICATE => (SignatureType::DsaSha1, EncryptionType::ElGamal),
+			KEY_CERTIFICATE => {
+				if payload.len() < 4 {
+					return Err(Error::Address("key certificate is too short".to_owned()));
+				}
+
+				let signature_code = u16::from_be_bytes([payload[0], payload[1]]);
+				let encryption_code = u16::from_be_bytes([payload[2], payload[3]]);
+
+				let signature_type = SignatureType::from_code(signature_code)
+					.ok_or_else(|| Error::Address(format!("unknown signature type {}", signature_code)))?;
+				let encryption_type = EncryptionType::from_code(encryption_code)
+					.ok_or_else(|| Error::Address(format!("unknown encryption type {}", encryption_code)))?;
+
+				let excess_length = signature_type.public_key_length().saturating_sub(SIGNING_KEY_FIELD_LENGTH)
+					+ encryption_type.public_key_length().saturating_sub(PUBLIC_KEY_FIELD_LENGTH);
+
+				if payload.len() < 4 + excess_length {
+					return Err(Error::Address("key certificate is missing key data".to_owned()));
+				}
+
+				(signature_type, encryption_type)
+			}
+			other => return Err(Error::Address(format!("unsupported certificate type {}", other))),
+		};
+
+		Ok((
+			Self {
+				bytes: bytes[..length].to_vec(),
+				signature_type,
+				encryption_type,
+			},
+			length,
+		))
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		self.bytes.clone()
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	pub fn to_base64(&self) -> String {
+		encode_base64(&self.bytes)
+	}
+
+	pub fn signature_type(&self) -> SignatureType {
+		self.signature_type
+	}
+
+	pub fn encryption_type(&self) -> EncryptionType {
+		self.encryption_type
+	}
+
+	/// The encryption public key, without padding.
+	pub fn public_key(&self) -> &[u8] {
+		&self.bytes[..self.encryption_type.public_key_length().min(PUBLIC_KEY_FIELD_LENGTH)]
+	}
+
+	/// The signing public key, without padding and including any part stored in the key certificate.
+	pub fn signing_public_key(&self) -> Vec<u8> {
+		let length = self.signature_type.public_key_length();
+		let field = &self.bytes[PUBLIC_KEY_FIELD_LENGTH..PUBLIC_KEY_FIELD_LENGTH + SIGNING_KEY_FIELD_LENGTH];
+
+		if length <= SIGNING_KEY_FIELD_LENGTH {
+			return field[SIGNING_KEY_FIELD_LENGTH - length..].to_vec();
+		}
+
+		let mut key = field.to_vec();
+		let excess_start = PUBLIC_KEY_FIELD_LENGTH + SIGNING_KEY_FIELD_LENGTH + 3 + 4;
+		key.extend_from_slice(&self.bytes[excess_start..excess_start + length - SIGNING_KEY_FIELD_LENGTH]);
+
+		key
+	}
+
+	pub fn certificate(&self) -> Certificate {
+		let header_length = PUBLIC_KEY_FIELD_LENGTH + SIGNING_KEY_FIELD_LENGTH;
+
+		Certificate {
+			certificate_type: self.bytes[header_length],
+			payload: self.bytes[header_length + 3..].to_vec(),
+		}
+	}
+
+	/// SHA-256 of the destination, which is what `.b32.i2p` addresses encode.
+	pub fn hash(&self) -> [u8; 32] {
+		Sha256::digest(&self.bytes).into()
+	}
+
+	/// The destination's `.b32.i2p` address.
+	pub fn b32_address(&self) -> B32Address {
+		B32Address::from(self)
+	}
+}
+
+impl FromStr for Destination {
+	type Err = Error;
+
+	fn from_str(base64: &str) -> Result<Self> {
+		Self::from_base64(base64)
+	}
+}
+
+impl fmt::Display for Destination {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		formatter.write_str(&self.to_base64())
+	}
+}
+
+impl From<Destination> for String {
+	fn from(destination: Destination) -> Self {
+		destination.to_base64()
+	}
+}
+
+impl From<&Destination> for String {
+	fn from(destination: &Destination) -> Self {
+		destination.to_base64()
+	}
+}
+
+/// A destination together with its private keys, as returned in DEST GENERATE's PRIV and accepted by
+/// SESSION CREATE's DESTINATION.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PrivateKeys {
+	destination: Destination,
+	private_key: Vec<u8>,
+	signing_private_key: Vec<u8>,
+	/// Offline signature block following an all-zero signing private key, kept as is.
+	offline_signature: Option<Vec<u8>>,
+}
+
+impl PrivateKeys {
+	/// Generates a new identity locally, without the router.
+	///
+	/// The destination gets a key certificate for `signature_type` and a real ECIES-X25519 key pair.
+	pub fn generate(signature_type: SignatureType) -> Result<Self> {
+		Self::generate_with_encryption(signature_type, EncryptionType::X25519)
+	}
+
+	/// Like [`PrivateKeys::generate`], with `encryption_type` for the destination's encryption key.
+	///
+	/// Only Ed25519 signing keys can be generated locally. An ElGamal key is only random padding with a zeroed private
+	/// key, so sessions using it must publish ECIES-X25519 leasesets alone (`i2cp.leaseSetEncType=4`).
+	pub fn generate_with_encryption(signature_type: SignatureType, encryption_type: EncryptionType) -> Result<Self> {
+		if signature_type != SignatureType::EdDsaSha512Ed25519 {
+			return Err(Error::Address(format!("cannot generate {:?} keys locally", signature_type)));
+		}
+
+		let mut public_key = vec![0u8; PUBLIC_KEY_FIELD_LENGTH];
+		OsRng.fill_bytes(&mut public_key);
+
+		let private_key = match encryption_type {
+			EncryptionType::ElGamal => vec![0u8; encryption_type.private_key_length()],
+			EncryptionType::X25519 => {
+				let secret = x25519_dalek::StaticSecret::random_from_rng(OsRng);
+				public_key[..32].copy_from_slice(x25519_dalek::PublicKey::from(&secret).as_bytes());
+
+				secret.to_bytes().to_vec()
+			}
+			other => return Err(Error::Address(format!("cannot generate {:?} keys locally", other))),
+		};
+
+		let signing_key = ed25519_dalek::SigningKey::generate(&mut OsRng);
+
+		let mut signing_key_field = vec![0u8; SIGNING_KEY_FIELD_LENGTH - signature_type.public_key_length()];
+		OsRng.fill_bytes(&mut signing_key_field);
+		signing_key_field.extend_from_slice(signing_key.verifying_key().as_bytes());
+
+		let mut bytes = public_key;
+		bytes.extend_from_slice(&signing_key_field);
+		bytes.push(KEY_CERTIFICATE);
+		bytes.extend_from_slice(&4u16.to_be_bytes());
+		bytes.extend_from_slice(&signature_type.code().to_be_bytes());
+		bytes.extend_from_slice(&encryption_type.code().to_be_bytes());
+
+		Self::from_parts(Destination::from_bytes(&bytes)?, private_key, signing_key.to_bytes().to_vec())
+	}
+
+	pub fn from_base64(base64: &str) -> Result<Self> {
+		Self::from_bytes(&decode_base64(base64)?)
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+		let (destination, destination_length) = Destination::read(bytes)?;
+
+		let private_key_end = destination_length + destination.encryption_type().private_key_length();
+		let signing_private_key_end = private_key_end + destination.signature_type().private_key_length();
+
+		if bytes.len() < signing_private_key_end {
+			return Err(Error::Address(format!("private keys are too short: {} bytes", bytes.len())));
+		}
+
+		let signing_private_key = bytes[private_key_end..signing_private_key_end].to_vec();
+		let rest = &bytes[signing_private_key_end..];
+
+		let offline_signature = if rest.is_empty() {
+			None
+		} else if signing_private_key.iter().all(|byte| *byte == 0) {
+			Some(rest.to_vec())
+		} else {
+			return Err(Error::Address(format!("{} unexpected bytes after private keys", rest.len())));
+		};
+
+		Ok(Self {
+			private_key: bytes[destination_length..private_key_end].to_vec(),
+			signing_private_key,
+			offline_signature,
+			destination,
+		})
+	}
+
+	/// Assembles private keys from their parts, checking each part's length against the destination's key types.
+	pub fn from_parts(destination: Destination, private_key: Vec<u8>, signing_private_key: Vec<u8>) -> Result<Self> {
+		if private_key.len() != destination.encryption_type().private_key_length() {
+			return Err(Error::Address(format!(
+				"private key should be {} bytes",
+				destination.encryption_type().private_key_length()
+			)));
+		}
+
+		if signing_private_key.len() != destination.signature_type().private_key_length() {
+			return Err(Error::Address(format!(
+				"signing private key should be {} bytes",
+				destination.signature_type().private_key_length()
+			)));
+		}
+
+		Ok(Self {
+			destination,
+			private_key,
+			signing_private_key,
+			offline_signature: None,
+		})
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = self.destination.to_bytes();
+		bytes.extend_from_slice(&self.private_key);
+		bytes.extend_from_slice(&self.signing_private_key);
+
+		if let Some(offline_signature) = &self.offline_signature {
+			bytes.extend_from_slice(offline_signature);
+		}
+
+		bytes
+	}
+
+	pub fn to_base64(&self) -> String {
+		encode_base64(&self.to_bytes())
+	}
+
+	pub fn destination(&self) -> &Destination {
+		&self.destination
+	}
+
+	/// The encryption private key.
+	pub fn private_key(&self) -> &[u8] {
+		&self.private_key
+	}
+
+	pub fn signing_private_key(&self) -> &[u8] {
+		&self.signing_private_key
+	}
+
+	pub fn offline_signature(&self) -> Option<&[u8]> {
+		self.offline_signature.as_deref()
+	}
+}
+
+impl FromStr for PrivateKeys {
+	type Err = Error;
+
+	fn from_str(base64: &str) -> Result<Self> {
+		Self::from_base64(base64)
+	}
+}
+
+/// Shows only the destination, so private keys don't end up in logs.
+impl fmt::Debug for PrivateKeys {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		formatter
+			.debug_struct("PrivateKeys")
+			.field("destination", &self.destination.to_base64())
+			.finish_non_exhaustive()
+	}
+}
+
+fn base64_encoding() -> Encoding {
+	let mut specification = Specification::new();
+	specification
+		.symbols
+		.push_str("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-~");
+	specification.padding = Some('=');
+
+	specification.encoding().unwrap()
+}
+
+/// Decodes I2P's base64, which uses `-` and `~` instead of `+` and `/`. Missing padding is tolerated.
+pub(crate) fn decode_base64(base64: &str) -> Result<Vec<u8>> {
+	let mut padded = base64.trim().to_owned();
+
+	while !padded.len().is_multiple_of(4) {
+		padded.push('=');
+	}
+
+	Ok(base64_encoding().decode(padded.as_bytes())?)
+}
+
+pub(crate) fn encode_base64(bytes: &[u8]) -> String {
+	base64_encoding().encode(bytes)
+}
@@ -0,0 +1,218 @@
+use crate::SamMessage;
+
+use std::fmt;
+
+/// Builds an [`Error::Protocol`] from a format string.
+macro_rules! protocol_error {
+	($($argument:tt)*) => {
+		$crate::Error::Protocol(format!($($argument)*))
+	};
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while talking to the SAM bridge.
+#[derive(Debug)]
+pub enum Error {
+	/// Reading from or writing to the bridge, or a keyfile, failed.
+	Io(std::io::Error),
+	/// The bridge sent something that isn't valid SAM, or not what was asked for.
+	Protocol(String),
+	/// The bridge answered with a RESULT other than OK.
+	Sam { result: SamResult, message: Option<String> },
+	/// NAMING LOOKUP failed: KEY_NOT_FOUND if nothing goes by `name`, INVALID_KEY if it is not a valid name at all.
+	Lookup {
+		name: String,
+		result: SamResult,
+		message: Option<String>,
+	},
+	/// The operation did not finish within its configured timeout.
+	Timeout(Operation),
+	/// A command was cancelled while it was being sent, so the control connection was closed: the session has to be
+	/// created again.
+	Interrupted,
+	/// A key, destination or address could not be decoded.
+	Address(String),
+	/// A keyfile is in the wrong format, or could not be decrypted with the passphrase given.
+	Keyfile(String),
+}
+
+impl Error {
+	/// Returns the RESULT code if the bridge refused the command.
+	pub fn sam_result(&self) -> Option<&SamResult> {
+		match self {
+			Self::Sam { result, .. } | Self::Lookup { result, .. } => Some(result),
+			_ => None,
+		}
+	}
+
+	pub fn is_timeout(&self) -> bool {
+		matches!(self, Self::Timeout(_)) || self.sam_result() == Some(&SamResult::Timeout)
+	}
+
+	/// Whether a lookup failed because nothing goes by the name.
+	pub fn is_not_found(&self) -> bool {
+		matches!(
+			self,
+			Self::Lookup {
+				result: SamResult::KeyNotFound,
+				..
+			}
+		)
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(error) => write!(formatter, "I/O error: {}", error),
+			Self::Protocol(message) => write!(formatter, "SAM protocol error: {}", message),
+			Self::Sam {
+				result,
+				message: Some(message),
+			} => write!(formatter, "SAM bridge returned {}: {}", result, message),
+			Self::Sam { result, message: None } => write!(formatter, "SAM bridge returned {}", result),
+			Self::Lookup {
+				name,
+				result,
+				message: Some(message),
+			} => write!(formatter, "could not look up {}: {}: {}", name, result, message),
+			Self::Lookup {
+				name,
+				result,
+				message: None,
+			} => write!(formatter, "could not look up {}: {}", name, result),
+			Self::Timeout(operation) => write!(formatter, "timed out waiting for {}", operation),
+			Self::Interrupted => write!(formatter, "a command was cut off while being sent to SAM"),
+			Self::Address(message) => write!(formatter, "invalid address: {}", message),
+			Self::Keyfile(message) => write!(formatter, "invalid keyfile: {}", message),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for Error {
+	fn from(error: std::io::Error) -> Self {
+		Self::Io(error)
+	}
+}
+
+impl From<data_encoding::DecodeError> for Error {
+	fn from(error: data_encoding::DecodeError) -> Self {
+		Self::Address(error.to_string())
+	}
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+	fn from(error: std::string::FromUtf8Error) -> Self {
+		Self::Protocol(error.to_string())
+	}
+}
+
+/// The kinds of exchange with the bridge that have their own timeout.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operation {
+	Connect,
+	Hello,
+	DestGenerate,
+	SessionCreate,
+	StreamConnect,
+	NamingLookup,
+	/// Any command without a dedicated timeout, such as STREAM FORWARD.
+	Other,
+}
+
+impl Operation {
+	/// Works out which operation a command belongs to.
+	pub fn of(command: &SamMessage) -> Self {
+		match (command.topic.as_str(), command.subtopic.as_deref()) {
+			("HELLO", _) => Self::Hello,
+			("DEST", Some("GENERATE")) => Self::DestGenerate,
+			("SESSION", Some("CREATE")) => Self::SessionCreate,
+			("STREAM", Some("CONNECT")) => Self::StreamConnect,
+			("NAMING", Some("LOOKUP")) => Self::NamingLookup,
+			_ => Self::Other,
+		}
+	}
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		formatter.write_str(match self {
+			Self::Connect => "connection to the SAM bridge",
+			Self::Hello => "HELLO",
+			Self::DestGenerate => "DEST GENERATE",
+			Self::SessionCreate => "SESSION CREATE",
+			Self::StreamConnect => "STREAM CONNECT",
+			Self::NamingLookup => "NAMING LOOKUP",
+			Self::Other => "SAM command",
+		})
+	}
+}
+
+/// The RESULT codes defined by SAMv3, plus whatever else a bridge might send.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SamResult {
+	CantReachPeer,
+	DuplicatedDest,
+	DuplicatedId,
+	I2pError,
+	InvalidId,
+	InvalidKey,
+	KeyNotFound,
+	PeerNotFound,
+	Timeout,
+	NoVersion,
+	AlreadyAccepting,
+	Other(String),
+}
+
+impl SamResult {
+	pub fn from_code(code: &str) -> Self {
+		match code {
+			"CANT_REACH_PEER" => Self::CantReachPeer,
+			"DUPLICATED_DEST" => Self::DuplicatedDest,
+			"DUPLICATED_ID" => Self::DuplicatedId,
+			"I2P_ERROR" => Self::I2pError,
+			"INVALID_ID" => Self::InvalidId,
+			"INVALID_KEY" => Self::InvalidKey,
+			"KEY_NOT_FOUND" => Self::KeyNotFound,
+			"PEER_NOT_FOUND" => Self::PeerNotFound,
+			"TIMEOUT" => Self::Timeout,
+			"NOVERSION" => Self::NoVersion,
+			"ALREADY_ACCEPTING" => Self::AlreadyAccepting,
+			other => Self::Other(other.to_owned()),
+		}
+	}
+
+	pub fn as_code(&self) -> &str {
+		match self {
+			Self::CantReachPeer => "CANT_REACH_PEER",
+			Self::DuplicatedDest => "DUPLICATED_DEST",
+			Self::DuplicatedId => "DUPLICATED_ID",
+			Self::I2pError => "I2P_ERROR",
+			Self::InvalidId => "INVALID_ID",
+			Self::InvalidKey => "INVALID_KEY",
+			Self::KeyNotFound => "KEY_NOT_FOUND",
+			Self::PeerNotFound => "PEER_NOT_FOUND",
+			Self::Timeout => "TIMEOUT",
+			Self::NoVersion => "NOVERSION",
+			Self::AlreadyAccepting => "ALREADY_ACCEPTING",
+			Self::Other(code) => code,
+		}
+	}
+}
+
+impl fmt::Display for SamResult {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		formatter.write_str(self.as_code())
+	}
+}
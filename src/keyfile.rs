@@ -0,0 +1,158 @@
+use crate::*;
+
+use std::io::Write;
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand_core::{OsRng, RngCore};
+
+/// Starts every encrypted keyfile, followed by the format version.
+const ENCRYPTED_MAGIC: &[u8] = b"solitude";
+const ENCRYPTED_VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+/// How far the Argon2 costs in a keyfile may exceed the defaults. They are read before anything is authenticated, so a
+/// crafted file could otherwise make loading it take all memory or never finish.
+const MAX_COST_FACTOR: u32 = 8;
+
+/// Magic, version, the three Argon2 costs, salt and nonce; all of it is authenticated along with the keys.
+const ENCRYPTED_HEADER_LENGTH: usize = 8 + 1 + 12 + SALT_LENGTH + NONCE_LENGTH;
+
+/// Reading and writing keyfiles.
+///
+/// Plain keyfiles hold the same bytes as i2pd's and Java I2P's `.dat` private key files, so they can be moved between
+/// them. Encrypted keyfiles hold those bytes sealed with ChaCha20-Poly1305, under a key derived from a passphrase with
+/// Argon2id.
+impl PrivateKeys {
+	/// Loads a plain `.dat` keyfile.
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let bytes = std::fs::read(path)?;
+
+		if bytes.starts_with(ENCRYPTED_MAGIC) {
+			return Err(Error::Keyfile("keyfile is encrypted and needs a passphrase".to_owned()));
+		}
+
+		Self::from_bytes(&bytes)
+	}
+
+	/// Saves the keys as a plain `.dat` keyfile, readable only by the current user where the platform allows.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		write_private(path.as_ref(), &self.to_bytes())
+	}
+
+	/// Loads a keyfile written by [`PrivateKeys::save_encrypted`].
+	pub fn load_encrypted<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self> {
+		Self::from_encrypted_bytes(&std::fs::read(path)?, passphrase)
+	}
+
+	/// Saves the keys encrypted with `passphrase`.
+	pub fn save_encrypted<P: AsRef<Path>>(&self, path: P, passphrase: &str) -> Result<()> {
+		write_private(path.as_ref(), &self.to_encrypted_bytes(passphrase)?)
+	}
+
+	/// Encrypts the keys with `passphrase`, in the format of an encrypted keyfile.
+	pub fn to_encrypted_bytes(&self, passphrase: &str) -> Result<Vec<u8>> {
+		let mut salt = [0u8; SALT_LENGTH];
+		let mut nonce = [0u8; NONCE_LENGTH];
+		OsRng.fill_bytes(&mut salt);
+		OsRng.fill_bytes(&mut nonce);
+
+		let params = Params::default();
+
+		let mut bytes = ENCRYPTED_MAGIC.to_vec();
+		bytes.push(ENCRYPTED_VERSION);
+		bytes.extend_from_slice(&params.m_cost().to_be_bytes());
+		bytes.extend_from_slice(&params.t_cost().to_be_bytes());
+		bytes.extend_from_slice(&params.p_cost().to_be_bytes());
+		bytes.extend_from_slice(&salt);
+		bytes.extend_from_slice(&nonce);
+
+		let cipher = cipher(passphrase, &salt, params.m_cost(), params.t_cost(), params.p_cost())?;
+
+		let ciphertext = cipher
+			.encrypt(
+				Nonce::from_slice(&nonce),
+				Payload {
+					msg: &self.to_bytes(),
+					aad: &bytes,
+				},
+			)
+			.map_err(|_| Error::Keyfile("could not encrypt keys".to_owned()))?;
+
+		bytes.extend_from_slice(&ciphertext);
+
+		Ok(bytes)
+	}
+
+	/// Decrypts keys encrypted by [`PrivateKeys::to_encrypted_bytes`].
+	pub fn from_encrypted_bytes(bytes: &[u8], passphrase: &str) -> Result<Self> {
+		if !bytes.starts_with(ENCRYPTED_MAGIC) || bytes.len() < ENCRYPTED_HEADER_LENGTH {
+			return Err(Error::Keyfile("not an encrypted keyfile".to_owned()));
+		}
+
+		let (header, ciphertext) = bytes.split_at(ENCRYPTED_HEADER_LENGTH);
+
+		if header[8] != ENCRYPTED_VERSION {
+			return Err(Error::Keyfile(format!("unsupported keyfile version {}", header[8])));
+		}
+
+		let cost = |offset: usize| u32::from_be_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]]);
+		let salt = &header[21..21 + SALT_LENGTH];
+		let nonce = &header[21 + SALT_LENGTH..];
+
+		let (m_cost, t_cost, p_cost) = (cost(9), cost(13), cost(17));
+
+		if m_cost > Params::DEFAULT_M_COST * MAX_COST_FACTOR
+			|| t_cost > Params::DEFAULT_T_COST * MAX_COST_FACTOR
+			|| p_cost > Params::DEFAULT_P_COST * MAX_COST_FACTOR
+		{
+			return Err(Error::Keyfile(format!(
+				"Argon2 costs m={} t={} p={} are too high",
+				m_cost, t_cost, p_cost
+			)));
+		}
+
+		let plaintext = cipher(passphrase, salt, m_cost, t_cost, p_cost)?
+			.decrypt(
+				Nonce::from_slice(nonce),
+				Payload {
+					msg: ciphertext,
+					aad: header,
+				},
+			)
+			.map_err(|_| Error::Keyfile("wrong passphrase, or the keyfile is corrupted".to_owned()))?;
+
+		Self::from_bytes(&plaintext).map_err(|error| match error {
+			Error::Address(message) => Error::Keyfile(format!("decrypted keys are invalid: {}", message)),
+			error => error,
+		})
+	}
+}
+
+fn cipher(passphrase: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<ChaCha20Poly1305> {
+	let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|error| Error::Keyfile(error.to_string()))?;
+
+	let mut key = [0u8; 32];
+
+	Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+		.hash_password_into(passphrase.as_bytes(), salt, &mut key)
+		.map_err(|error| Error::Keyfile(error.to_string()))?;
+
+	Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+	options.open(path)?.write_all(bytes)?;
+
+	Ok(())
+}
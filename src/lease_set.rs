@@ -0,0 +1,67 @@
+use crate::destination::encode_base64;
+
+/// Publishes a session's leaseset encrypted (LS2 type 5).
+///
+/// Only clients that know the service's b33 address can find it, and, depending on the settings, only those that also
+/// know the secret or hold a key authorized here.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EncryptedLeaseSet {
+	/// Lookup password clients need besides the address.
+	pub secret: Option<String>,
+	pub auth: LeaseSetAuth,
+}
+
+/// Per-client authorization for an encrypted leaseset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LeaseSetAuth {
+	/// Anyone with the address, and the secret if set, can connect.
+	#[default]
+	None,
+	/// Clients authorized by their X25519 public keys.
+	Dh(Vec<ClientKey>),
+	/// Clients authorized by a pre-shared key each.
+	Psk(Vec<ClientKey>),
+}
+
+/// A client allowed to read an encrypted leaseset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientKey {
+	/// A label for the client; the router only uses it in its own logs and configuration. It is sent base64-encoded, so it
+	/// may hold any characters.
+	pub name: String,
+	pub key: [u8; 32],
+}
+
+impl ClientKey {
+	pub fn new<S: Into<String>>(name: S, key: [u8; 32]) -> Self {
+		Self { name: name.into(), key }
+	}
+}
+
+impl EncryptedLeaseSet {
+	/// The I2CP options SESSION CREATE carries for it.
+	pub fn options(&self) -> Vec<(String, String)> {
+		let mut options = vec![("i2cp.leaseSetType".to_owned(), "5".to_owned())];
+
+		if let Some(secret) = &self.secret {
+			options.push(("i2cp.leaseSetSecret".to_owned(), encode_base64(secret.as_bytes())));
+		}
+
+		let (auth_type, kind, clients) = match &self.auth {
+			LeaseSetAuth::None => return options,
+			LeaseSetAuth::Dh(clients) => (1, "dh", clients),
+			LeaseSetAuth::Psk(clients) => (2, "psk", clients),
+		};
+
+		options.push(("i2cp.leaseSetAuthType".to_owned(), auth_type.to_string()));
+
+		for (index, client) in clients.iter().enumerate() {
+			options.push((
+				format!("i2cp.leaseSetClient.{}.{}", kind, index),
+				format!("{}:{}", encode_base64(client.name.as_bytes()), encode_base64(&client.key)),
+			));
+		}
+
+		options
+	}
+}
@@ -1,87 +1,174 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc::channel;
+use tokio::sync::Mutex;
 
-#[macro_use]
-extern crate anyhow;
+use connection::SamConnection;
 
 #[macro_use]
 extern crate log;
 
-use anyhow::{Context, Result};
-use data_encoding::{Specification, BASE32};
-use sha2::{Digest, Sha256};
+#[macro_use]
+mod error;
+pub use error::{Error, Operation, Result, SamResult};
+
+mod destination;
+pub use destination::{Certificate, Destination, EncryptionType, PrivateKeys, SignatureType};
+
+mod keyfile;
+
+mod address;
+pub use address::{B32Address, B33Key};
+
+mod lease_set;
+pub use lease_set::{ClientKey, EncryptedLeaseSet, LeaseSetAuth};
+
+mod options;
+pub use options::SessionOptions;
+
+mod naming;
+pub use naming::NamingLookup;
+
+mod address_book;
+pub use address_book::{AddressBook, HostEntry};
+
+mod resolver;
+pub use resolver::{
+	CachedResolver, Resolution, Resolver, ResolverChain, DEFAULT_CACHE_CAPACITY, DEFAULT_CACHE_TTL, DEFAULT_NEGATIVE_CACHE_TTL,
+};
 
 mod datagram;
-pub use datagram::DatagramMessage;
+pub use datagram::{DatagramMessage, I2pDatagramSocket};
+
+mod raw;
+pub use raw::{RawDatagramMessage, RawDatagramSocket};
 
 mod stream;
-pub use stream::StreamInfo;
+pub use stream::{I2pReadHalf, I2pStream, I2pWriteHalf, StreamInfo};
+
+mod message;
+pub use message::SamMessage;
+
+mod connection;
+
+mod listener;
+pub use listener::{StreamListener, DEFAULT_ACCEPT_POOL_SIZE};
+
+mod primary;
+pub use primary::PrimarySession;
+use primary::Subsession;
+
+#[cfg(feature = "bridge")]
+pub mod bridge;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+mod builder;
+pub use builder::{
+	SamConfig, SessionBuilder, Timeouts, DATAGRAM_ADDRESS_VARIABLE, DEFAULT_DATAGRAM_ADDRESS, DEFAULT_SAM_ADDRESS, SAM_ADDRESS_VARIABLE,
+};
 
-use tokio::io::AsyncBufReadExt;
-use tokio::io::AsyncReadExt;
-use tokio::io::AsyncWriteExt;
 /// Creates a SAMv3 session with local i2p daemon.
 ///
-/// Forwards all connections to a server supplied by the user.
+/// The session is owned by a single control connection to the bridge, which lives as long as the `Session`.
+/// Every stream that is connected, accepted or forwarded gets a connection of its own, so one session can carry
+/// any number of concurrent streams.
+///
+/// Subsessions of a [`PrimarySession`] are `Session`s too, sharing the primary's control connection.
 #[derive(Debug)]
 pub struct Session {
-	stream: tokio::io::BufStream<tokio::net::TcpStream>,
+	control: Arc<Mutex<SamConnection>>,
+	/// Whether SESSION CREATE, or SESSION ADD for a subsession, has succeeded.
+	created: AtomicBool,
+	subsession: Option<Subsession>,
+	config: SamConfig,
 	session_style: SessionStyle,
-	pub public_key: String,
-	pub private_key: String,
+	/// Connections that have issued STREAM FORWARD; the forwarding stops when they are closed.
+	forwarders: Vec<SamConnection>,
+	options: SessionOptions,
+	pub public_key: Destination,
+	pub private_key: PrivateKeys,
 	pub service: String,
 }
 
 impl Session {
-	/// Creates a session that has only done HELLO.
+	/// Creates a session with a freshly generated destination.
 	pub async fn new<S: Into<String>>(service: S, session_style: SessionStyle) -> Result<Self> {
-		let service_string = service.into();
-
-		trace!("creating new session with id {}", service_string);
-
-		let stream = tokio::net::TcpStream::connect("localhost:7656")
-			.await
-			.context("couldn't connect to local SAM bridge")?;
-		// 		stream.set_read_timeout(Some(Duration::from_secs(90)))?; TODO
-
-		let mut session = Session {
-			stream: tokio::io::BufStream::new(stream),
-			session_style: session_style.to_owned(),
-			public_key: String::new(),
-			private_key: String::new(),
-			service: service_string,
-		};
-
-		session.hello().await?;
-		session.keys().await?;
+		SessionBuilder::new(service, session_style).build().await
+	}
 
-		Ok(session)
+	/// Restores a session from a previously generated destination.
+	pub async fn from<S: Into<String>>(service: S, session_style: SessionStyle, private_keys: PrivateKeys) -> Result<Self> {
+		SessionBuilder::new(service, session_style).keys(private_keys).build().await
 	}
 
-	pub async fn from<S: Into<String>>(service: S, session_style: SessionStyle, public_key: S, private_key: S) -> Result<Self> {
-		let service_string = service.into();
-		let public_key_string = public_key.into();
+	/// Restores a session from a plain `.dat` keyfile, as written by [`PrivateKeys::save`], i2pd or Java I2P.
+	pub async fn from_keyfile<S: Into<String>, P: AsRef<std::path::Path>>(
+		service: S,
+		session_style: SessionStyle,
+		path: P,
+	) -> Result<Self> {
+		Self::from(service, session_style, PrivateKeys::load(path)?).await
+	}
 
-		trace!("restoring session with id {} and public_key {}", service_string, public_key_string);
+	/// Restores a session from a keyfile written by [`PrivateKeys::save_encrypted`].
+	pub async fn from_encrypted_keyfile<S: Into<String>, P: AsRef<std::path::Path>>(
+		service: S,
+		session_style: SessionStyle,
+		path: P,
+		passphrase: &str,
+	) -> Result<Self> {
+		Self::from(service, session_style, PrivateKeys::load_encrypted(path, passphrase)?).await
+	}
 
-		let stream = tokio::net::TcpStream::connect("localhost:7656")
-			.await
-			.context("couldn't connect to local SAM bridge")?;
-		// 		stream.set_read_timeout(Some(Duration::from_secs(90)))?; TODO
+	pub fn builder<S: Into<String>>(service: S, session_style: SessionStyle) -> SessionBuilder {
+		SessionBuilder::new(service, session_style)
+	}
 
-		let mut session = Session {
-			stream: tokio::io::BufStream::new(stream),
-			session_style: session_style.to_owned(),
-			public_key: public_key_string,
-			private_key: private_key.into(),
-			service: service_string,
+	/// Creates a session that has done HELLO and, unless `private_keys` are given, DEST GENERATE.
+	async fn connect(
+		service: String,
+		session_style: SessionStyle,
+		config: SamConfig,
+		private_keys: Option<PrivateKeys>,
+		signature_type: Option<SignatureType>,
+	) -> Result<Self> {
+		trace!("creating new session with id {} on {}", service, config.sam_address);
+
+		let mut connection = SamConnection::connect(&service, &config).await?;
+
+		let private_keys = match private_keys {
+			Some(private_keys) => private_keys,
+			None => generate_keys(&service, &mut connection, signature_type).await?,
 		};
 
-		session.hello().await?;
+		Ok(Session {
+			control: Arc::new(Mutex::new(connection)),
+			created: AtomicBool::new(false),
+			subsession: None,
+			config,
+			session_style,
+			forwarders: Vec::new(),
+			options: SessionOptions::default(),
+			public_key: private_keys.destination().clone(),
+			private_key: private_keys,
+			service,
+		})
+	}
+
+	pub fn config(&self) -> &SamConfig {
+		&self.config
+	}
 
-		Ok(session)
+	pub fn options(&self) -> &SessionOptions {
+		&self.options
 	}
 
+	/// Forwards the session to `forwarding_address:port`.
+	///
+	/// Datagram and raw sessions are created with that address as their datagram destination. Stream sessions
+	/// forward every incoming stream to a TCP server there, for as long as the `Session` is alive.
 	pub async fn forward<S: Into<String>>(&mut self, forwarding_address: S, port: u16) -> Result<()> {
 		let forwarding_address_string = forwarding_address.into();
 
@@ -89,191 +176,289 @@ impl Session {
 
 		match self.session_style {
 			SessionStyle::Datagram | SessionStyle::Raw => {
-				self.command(&format!(
-					"SESSION CREATE STYLE={} ID={} DESTINATION={} PORT={} HOST={}\n",
-					self.session_style.as_string(),
-					&self.service,
-					&self.private_key,
-					port,
-					forwarding_address_string
-				))
-				.await?;
+				self.forward_datagrams(forwarding_address_string, port, false).await?;
 			}
 			SessionStyle::Stream => {
-				self.command(&format!(
-					"SESSION CREATE STYLE={} ID={} DESTINATION={}\n",
-					self.session_style.as_string(),
-					self.service,
-					self.private_key
-				))
-				.await
-				.context("Could not create session")?;
-
-				let (sender, mut receiver) = channel::<Result<_>>(10); // TODO: size?
-
-				let new_service = self.service.clone();
-
-				tokio::task::spawn(async move {
-					let mut new_session = match Session::new(new_service.clone(), SessionStyle::Stream).await {
-						Ok(session) => session,
-						Err(error) => {
-							sender.send(Err(error)).await.unwrap();
-							return;
-						}
-					};
-
-					if let Err(error) = new_session
-						.command(&format!(
-							"STREAM FORWARD ID={} PORT={} HOST={}\n",
-							new_service, port, forwarding_address_string,
-						))
-						.await
-					{
-						sender.send(Err(error)).await.unwrap();
-						return;
-					}
-
-					sender.send(Ok(())).await.unwrap();
-
-					loop {
-						let mut buffer = [];
-						let read = new_session.stream.read(&mut buffer).await;
-
-						if let Err(error) = read {
-							panic!("stream forwarder closed with: {}", error);
-						}
-
-						tokio::time::sleep(Duration::from_secs(2)).await;
-					}
-				});
-
-				for _ in 0..1 {
-					receiver.recv().await.unwrap()?;
-				}
+				self.create(self.session_create_command()).await?;
+
+				let mut connection = SamConnection::connect(&self.service, &self.config).await?;
+
+				connection
+					.command(
+						&SamMessage::new("STREAM", "FORWARD")
+							.with("ID", &self.service)
+							.with("PORT", port)
+							.with("HOST", forwarding_address_string),
+					)
+					.await?;
+
+				self.forwarders.push(connection);
 			}
 		};
 
 		Ok(())
 	}
 
-	/// Returns a TcpStream connected to the destination.
-	pub async fn connect_stream<S: Into<String>>(&mut self, destination: S) -> Result<tokio::io::BufStream<tokio::net::TcpStream>> {
-		self.command(&format!(
-			"SESSION CREATE STYLE=STREAM ID={} DESTINATION={}\n",
-			self.service, self.private_key,
-		))
-		.await
-		.context("Couldn't create session")?;
+	/// Creates a datagram or raw session that the bridge forwards to `address:port`.
+	///
+	/// With `header`, raw sessions receive a line with FROM_PORT, TO_PORT and PROTOCOL before each payload.
+	pub(crate) async fn forward_datagrams(&mut self, address: String, port: u16, header: bool) -> Result<()> {
+		let mut session_create = self.session_create_command().with("PORT", port).with("HOST", address);
+
+		if header {
+			session_create.push("HEADER", "true");
+		}
 
-		let mut connected_session = Session::new(self.service.to_owned(), SessionStyle::Stream).await?;
+		if !self.create(session_create).await? {
+			return Err(protocol_error!("session {} is already forwarding", self.service));
+		}
 
-		connected_session
-			.command(&format!("STREAM CONNECT ID={} DESTINATION={}\n", self.service, destination.into()))
-			.await?;
+		Ok(())
+	}
 
-		Ok(connected_session.stream)
+	/// Returns a stream connected to the destination, given in base64.
+	///
+	/// Can be called any number of times, concurrently, on the same session.
+	pub async fn connect_stream<S: Into<String>>(&self, destination: S) -> Result<I2pStream> {
+		self.connect_stream_with_ports(destination, 0, 0).await
 	}
 
-	async fn hello(&mut self) -> Result<()> {
-		debug!("sam connection with ID {} is executing hello", self.service);
+	/// Like [`Session::connect_stream`], from I2P port `from_port` to port `to_port` of the destination.
+	///
+	/// Ports other than 0 need a SAM 3.2 bridge.
+	pub async fn connect_stream_with_ports<S: Into<String>>(&self, destination: S, from_port: u16, to_port: u16) -> Result<I2pStream> {
+		if self.session_style != SessionStyle::Stream {
+			return Err(protocol_error!("only stream sessions can connect streams"));
+		}
+
+		let destination = Destination::from_base64(&destination.into())?;
 
-		let expression = regex::Regex::new(r#"HELLO REPLY RESULT=OK VERSION=(.*)\n"#)?;
+		self.create(self.session_create_command()).await?;
 
-		if !expression.is_match(&self.command("HELLO VERSION MIN=3.0 MAX=3.2\n").await?) {
-			bail!("didn't receive a hello response from i2p");
+		let mut connection = SamConnection::connect(&self.service, &self.config).await?;
+
+		let mut stream_connect = SamMessage::new("STREAM", "CONNECT")
+			.with("ID", &self.service)
+			.with("DESTINATION", &destination);
+
+		if from_port != 0 || to_port != 0 {
+			if !connection.supports(3, 2) {
+				return Err(protocol_error!("stream ports need a SAM 3.2 bridge"));
+			}
+
+			stream_connect.push("FROM_PORT", from_port);
+			stream_connect.push("TO_PORT", to_port);
 		}
 
-		Ok(())
+		connection.command(&stream_connect).await?;
+
+		Ok(I2pStream::new(
+			connection.into_stream(),
+			self.public_key.clone(),
+			destination,
+			from_port,
+			to_port,
+		))
 	}
 
-	async fn keys(&mut self) -> Result<()> {
-		debug!("sam connection with ID {} is getting keys", self.service);
+	/// Returns a stream connected to a hostname, b32 address or base64 destination, resolving names with `resolver`.
+	pub async fn connect_stream_by_name<R: Resolver + ?Sized>(&self, name: &str, resolver: &R) -> Result<I2pStream> {
+		let destination = match Destination::from_base64(name) {
+			Ok(destination) => destination,
+			Err(_) => resolver.resolve(name).await?.ok_or_else(|| Error::Lookup {
+				name: name.to_owned(),
+				result: SamResult::KeyNotFound,
+				message: None,
+			})?,
+		};
 
-		let expression = regex::Regex::new(r#"DEST REPLY PUB=(?P<public>[^ ]*) PRIV=(?P<private>[^\n]*)"#)?;
+		self.connect_stream(destination).await
+	}
 
-		let body = &self.command("DEST GENERATE\n").await?;
-		let matches = expression.captures(body).context("invalid response")?;
+	/// Accepts incoming streams with STREAM ACCEPT, keeping [`DEFAULT_ACCEPT_POOL_SIZE`] ACCEPTs pending.
+	pub async fn listen(&self) -> Result<StreamListener> {
+		self.listen_with_pool_size(DEFAULT_ACCEPT_POOL_SIZE).await
+	}
 
-		self.public_key = matches.name("public").context("no public key")?.as_str().to_string();
-		self.private_key = matches.name("private").context("no private key")?.as_str().to_string();
+	/// Accepts incoming streams with STREAM ACCEPT, keeping `pool_size` ACCEPTs pending.
+	///
+	/// Bridges older than SAM 3.2 allow only one pending ACCEPT, so the pool is shrunk to one for them.
+	pub async fn listen_with_pool_size(&self, pool_size: usize) -> Result<StreamListener> {
+		if self.session_style != SessionStyle::Stream {
+			return Err(protocol_error!("only stream sessions can accept streams"));
+		}
 
-		Ok(())
+		debug!("sam connection with ID {} is listening", self.service);
+
+		self.create(self.session_create_command()).await?;
+
+		let pool_size = if self.control.lock().await.supports(3, 2) {
+			pool_size.max(1)
+		} else {
+			1
+		};
+
+		Ok(StreamListener::new(
+			self.service.clone(),
+			self.public_key.clone(),
+			self.config.clone(),
+			pool_size,
+		))
 	}
 
-	pub fn address(&self) -> Result<String> {
-		let public_key_bytes = decode_base_64(&self.public_key)?;
+	/// The SESSION CREATE command for this session, or SESSION ADD for a subsession, with its options but before any
+	/// style-specific ones.
+	fn session_create_command(&self) -> SamMessage {
+		let mut session_create = match &self.subsession {
+			None => SamMessage::new("SESSION", "CREATE")
+				.with("STYLE", self.session_style.as_string())
+				.with("ID", &self.service)
+				.with("DESTINATION", self.private_key.to_base64()),
+			Some(subsession) => SamMessage::new("SESSION", "ADD")
+				.with("STYLE", self.session_style.as_string())
+				.with("ID", &self.service)
+				.with("LISTEN_PORT", subsession.listen_port),
+		};
+
+		for (key, value) in self.options.options() {
+			session_create.push(key, value);
+		}
+
+		session_create
+	}
+
+	/// Sends SESSION CREATE on the control connection, unless an earlier call already did.
+	///
+	/// Returns whether this call created the session.
+	async fn create(&self, session_create: SamMessage) -> Result<bool> {
+		let mut control = self.control.lock().await;
 
-		let mut hasher = Sha256::new();
+		if self.created.load(Ordering::Acquire) {
+			return Ok(false);
+		}
 
-		hasher.update(public_key_bytes);
+		control.command(&session_create).await?;
+		self.created.store(true, Ordering::Release);
 
-		let address = BASE32.encode(&hasher.finalize()).to_lowercase();
+		Ok(true)
+	}
 
-		Ok(address.trim_end_matches('=').to_owned() + ".b32.i2p")
+	pub fn address(&self) -> Result<String> {
+		Ok(self.public_key.b32_address().to_string())
 	}
 
-	pub async fn close(mut self) -> Result<()> {
+	/// Closes the control connection and every forwarder, which ends the session on the bridge.
+	///
+	/// Subsessions are removed with SESSION REMOVE instead, leaving the primary session and its other subsessions open.
+	pub async fn close(self) -> Result<()> {
 		debug!("sam connection with ID {} is closing i2p", self.service);
 
-		self.stream.shutdown().await?;
+		if self.subsession.is_some() && self.created.load(Ordering::Acquire) {
+			self.command(&SamMessage::new("SESSION", "REMOVE").with("ID", &self.service))
+				.await?;
+		}
 
-		Ok(())
+		for mut forwarder in self.forwarders {
+			forwarder.shutdown().await?;
+		}
+
+		if self.subsession.is_some() {
+			return Ok(());
+		}
+
+		self.control.lock().await.shutdown().await
 	}
 
-	async fn command(&mut self, command: &str) -> Result<String> {
-		debug!("sam connection with ID {} is executing command {}", self.service, command);
+	async fn command(&self, command: &SamMessage) -> Result<SamMessage> {
+		self.control.lock().await.command(command).await
+	}
 
-		self.stream.write_all(command.as_bytes()).await?;
-		self.stream.flush().await?;
-		let mut response = String::new();
+	/// Resolves a hostname, a b32 or b33 address, or `ME` for the session's own destination.
+	///
+	/// Fails with [`Error::Lookup`], whose RESULT is KEY_NOT_FOUND if nothing goes by the name and INVALID_KEY if it is
+	/// not a valid name.
+	pub async fn look_up<S: Into<String>>(&self, name: S) -> Result<Destination> {
+		Ok(self.naming_lookup(name.into(), false).await?.destination)
+	}
 
-		trace!("reading from SAM socket");
-		self.stream.read_line(&mut response).await?;
-		trace!("read from SAM socket");
+	/// Like [`Session::look_up`], but also asks a SAM 3.2 bridge for the name's properties, such as the ones in its
+	/// address book entry.
+	pub async fn look_up_with_options<S: Into<String>>(&self, name: S) -> Result<NamingLookup> {
+		if !self.control.lock().await.supports(3, 2) {
+			return Err(protocol_error!("naming lookup options need a SAM 3.2 bridge"));
+		}
 
-		trace!(
-			"sam connection with ID {} sent command {} and got response {}",
-			self.service,
-			command,
-			response
-		);
+		self.naming_lookup(name.into(), true).await
+	}
 
-		let expression = regex::Regex::new(r#"(REPLY|STATUS)\s(RESULT=(?P<result>[^\s]*)(.*)|([^\n]*))"#)?;
+	async fn naming_lookup(&self, name: String, options: bool) -> Result<NamingLookup> {
+		debug!("sam connection with ID {} is looking up address {}", self.service, name);
 
-		let matches = expression.captures(&response).context("Could not regex SAMv3's response")?;
+		// The bridge only knows ME once the session exists, but it can only be this session's destination.
+		if name == "ME" && !self.created.load(Ordering::Acquire) {
+			return Ok(NamingLookup {
+				name,
+				destination: self.public_key.clone(),
+				options: Default::default(),
+			});
+		}
 
-		if let Some(result) = matches.name("result") {
-			let result_str = result.as_str();
+		let mut naming_lookup = SamMessage::new("NAMING", "LOOKUP").with("NAME", &name);
 
-			if result_str == "OK" {
-				Ok(response)
-			} else {
-				bail!(response)
-			}
-		} else {
-			Ok(response)
+		if options {
+			naming_lookup.push("OPTIONS", "true");
 		}
+
+		let reply = self.command(&naming_lookup).await.map_err(|error| match error {
+			Error::Sam { result, message } => Error::Lookup {
+				name: name.clone(),
+				result,
+				message,
+			},
+			error => error,
+		})?;
+
+		NamingLookup::from_reply(&name, &reply)
 	}
+}
+
+/// Sends DEST GENERATE, checking that the bridge honoured `signature_type`.
+pub(crate) async fn generate_keys(
+	service: &str,
+	connection: &mut SamConnection,
+	signature_type: Option<SignatureType>,
+) -> Result<PrivateKeys> {
+	debug!("sam connection with ID {} is getting keys", service);
 
-	pub async fn look_up<S: Into<String>>(&mut self, address: S) -> Result<String> {
-		let address_string = address.into();
+	let mut dest_generate = SamMessage::new("DEST", "GENERATE");
 
-		debug!("sam connection with ID {} is looking up address {}", self.service, address_string);
+	if let Some(signature_type) = signature_type {
+		if !connection.supports(3, 1) {
+			return Err(protocol_error!("choosing a signature type needs a SAM 3.1 bridge"));
+		}
 
-		let expression = regex::Regex::new(r#"NAMING REPLY RESULT=OK NAME=([^ ]*) VALUE=(?P<value>[^\n]*)\n"#)?;
+		dest_generate.push("SIGNATURE_TYPE", signature_type);
+	}
 
-		let body = self.command(&format!("NAMING LOOKUP NAME={}\n", address_string)).await?;
+	let reply = connection.command(&dest_generate).await?;
 
-		let matches = expression.captures(&body).context("could not resolve domain")?;
+	let private_keys = PrivateKeys::from_base64(reply.require("PRIV")?)?;
 
-		let value = matches
-			.name("value")
-			.context("no return value, possibly an invalid domain")?
-			.as_str()
-			.to_string();
+	if let Some(signature_type) = signature_type {
+		if private_keys.destination().signature_type() != signature_type {
+			return Err(protocol_error!(
+				"DEST GENERATE returned a {} destination instead of {}",
+				private_keys.destination().signature_type(),
+				signature_type
+			));
+		}
+	}
 
-		Ok(value)
+	if Destination::from_base64(reply.require("PUB")?)? != *private_keys.destination() {
+		return Err(protocol_error!("DEST GENERATE returned a PUB that does not match PRIV"));
 	}
+
+	Ok(private_keys)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -292,15 +477,3 @@ impl SessionStyle {
 		}
 	}
 }
-
-fn decode_base_64(base_64_code: &str) -> Result<Vec<u8>> {
-	let mut specification = Specification::new();
-	specification
-		.symbols
-		.push_str("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-~");
-
-	let encoder = specification.encoding().unwrap();
-	let buffer = encoder.decode(base_64_code.as_bytes())?;
-
-	Ok(buffer)
-}
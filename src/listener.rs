@@ -0,0 +1,115 @@
+use crate::*;
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::task::JoinHandle;
+
+/// Number of STREAM ACCEPTs [`Session::listen`] keeps pending.
+pub const DEFAULT_ACCEPT_POOL_SIZE: usize = 4;
+
+/// How long an acceptor waits before retrying after the bridge refused or dropped an ACCEPT.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+type Accepted = Result<(I2pStream, Destination)>;
+
+/// Receives incoming streams through STREAM ACCEPT, without a local TCP listener.
+///
+/// Each pending ACCEPT owns a connection to the bridge; as soon as one is handed out a new one takes its place.
+/// Dropping the listener closes all pending ACCEPTs.
+#[derive(Debug)]
+pub struct StreamListener {
+	receiver: Receiver<Accepted>,
+	acceptors: Vec<JoinHandle<()>>,
+}
+
+impl StreamListener {
+	pub(crate) fn new(service: String, local_destination: Destination, config: SamConfig, pool_size: usize) -> Self {
+		let (sender, receiver) = tokio::sync::mpsc::channel(pool_size);
+
+		let acceptors = (0..pool_size)
+			.map(|_| {
+				tokio::task::spawn(accept_loop(
+					service.clone(),
+					local_destination.clone(),
+					config.clone(),
+					sender.clone(),
+				))
+			})
+			.collect();
+
+		Self { receiver, acceptors }
+	}
+
+	/// Waits for the next incoming stream, returning it along with the peer's destination.
+	pub async fn accept(&mut self) -> Accepted {
+		self.receiver
+			.recv()
+			.await
+			.unwrap_or_else(|| Err(protocol_error!("stream listener has shut down")))
+	}
+}
+
+impl futures_core::Stream for StreamListener {
+	type Item = Accepted;
+
+	fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		self.receiver.poll_recv(context)
+	}
+}
+
+impl Drop for StreamListener {
+	fn drop(&mut self) {
+		for acceptor in &self.acceptors {
+			acceptor.abort();
+		}
+	}
+}
+
+async fn accept_loop(service: String, local_destination: Destination, config: SamConfig, sender: Sender<Accepted>) {
+	loop {
+		let accepted = accept_one(&service, &local_destination, &config).await;
+		let failed = accepted.is_err();
+
+		if let Err(error) = &accepted {
+			warn!("sam connection with ID {} failed to accept: {}", service, error);
+		}
+
+		if sender.send(accepted).await.is_err() {
+			return;
+		}
+
+		if failed {
+			tokio::time::sleep(RETRY_DELAY).await;
+		}
+	}
+}
+
+async fn accept_one(service: &str, local_destination: &Destination, config: &SamConfig) -> Accepted {
+	let mut connection = SamConnection::connect(service, config).await?;
+
+	connection
+		.command(&SamMessage::new("STREAM", "ACCEPT").with("ID", service).with("SILENT", "false"))
+		.await?;
+
+	let mut reader = connection.into_stream();
+	let stream_info = StreamInfo::from_bufread(&mut reader).await?;
+
+	debug!(
+		"sam connection with ID {} accepted a stream from {}",
+		service, stream_info.destination
+	);
+
+	let peer_destination = Destination::from_base64(&stream_info.destination)?;
+
+	let stream = I2pStream::new(
+		reader,
+		local_destination.clone(),
+		peer_destination.clone(),
+		stream_info.to_port.unwrap_or(0),
+		stream_info.from_port.unwrap_or(0),
+	);
+
+	Ok((stream, peer_destination))
+}
@@ -0,0 +1,199 @@
+use crate::*;
+
+use std::fmt;
+
+/// One line of the SAMv3 protocol, such as `HELLO REPLY RESULT=OK VERSION=3.1`.
+///
+/// Lines are made of a topic, an optional subtopic and an ordered list of `KEY=VALUE` pairs. Values may be
+/// double quoted, in which case they can contain spaces and the escapes `\"` and `\\`. A key without `=` is
+/// read as having an empty value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamMessage {
+	pub topic: String,
+	pub subtopic: Option<String>,
+	pub pairs: Vec<(String, String)>,
+}
+
+impl SamMessage {
+	pub fn new<S: Into<String>>(topic: S, subtopic: S) -> Self {
+		Self {
+			topic: topic.into(),
+			subtopic: Some(subtopic.into()),
+			pairs: Vec::new(),
+		}
+	}
+
+	/// Appends a `KEY=VALUE` pair, keeping insertion order.
+	pub fn with<K: Into<String>, V: ToString>(mut self, key: K, value: V) -> Self {
+		self.push(key, value);
+		self
+	}
+
+	pub fn push<K: Into<String>, V: ToString>(&mut self, key: K, value: V) {
+		self.pairs.push((key.into(), value.to_string()));
+	}
+
+	/// Returns the value of the first pair with this key.
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.pairs.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+	}
+
+	/// Like [`SamMessage::get`], but a missing key is an error.
+	pub fn require(&self, key: &str) -> Result<&str> {
+		self.get(key)
+			.ok_or_else(|| protocol_error!("{} {} is missing {}", self.topic, self.subtopic.as_deref().unwrap_or(""), key))
+	}
+
+	pub fn is(&self, topic: &str, subtopic: &str) -> bool {
+		self.topic == topic && self.subtopic.as_deref() == Some(subtopic)
+	}
+
+	/// Parses a single line, with or without its trailing newline.
+	pub fn parse(line: &str) -> Result<Self> {
+		let line = line.trim_end_matches(['\n', '\r']);
+		let mut tokens = tokenize(line)?.into_iter();
+
+		let topic = match tokens.next() {
+			Some(Token::Word(topic)) => topic,
+			Some(Token::Pair(..)) => return Err(protocol_error!("SAM message starts with a KEY=VALUE pair: {}", line)),
+			None => return Err(protocol_error!("empty SAM message")),
+		};
+
+		let mut message = Self {
+			topic,
+			subtopic: None,
+			pairs: Vec::new(),
+		};
+
+		for (index, token) in tokens.enumerate() {
+			match token {
+				Token::Word(subtopic) if index == 0 => message.subtopic = Some(subtopic),
+				Token::Word(key) => message.pairs.push((key, String::new())),
+				Token::Pair(key, value) => message.pairs.push((key, value)),
+			}
+		}
+
+		Ok(message)
+	}
+
+	/// Parses a line that only holds `KEY=VALUE` pairs, as found in forwarding and datagram headers.
+	pub fn parse_pairs(line: &str) -> Result<Vec<(String, String)>> {
+		let line = line.trim_end_matches(['\n', '\r']);
+
+		Ok(tokenize(line)?
+			.into_iter()
+			.map(|token| match token {
+				Token::Word(key) => (key, String::new()),
+				Token::Pair(key, value) => (key, value),
+			})
+			.collect())
+	}
+
+	/// Returns the line as it is sent to the bridge, newline included.
+	pub fn serialize(&self) -> String {
+		format!("{}\n", self)
+	}
+}
+
+impl fmt::Display for SamMessage {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(formatter, "{}", self.topic)?;
+
+		if let Some(subtopic) = &self.subtopic {
+			write!(formatter, " {}", subtopic)?;
+		}
+
+		for (key, value) in &self.pairs {
+			write!(formatter, " {}={}", key, quote(value))?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Quotes a value if it would not survive being written bare.
+pub(crate) fn quote(value: &str) -> String {
+	if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+		return value.to_owned();
+	}
+
+	let mut quoted = String::with_capacity(value.len() + 2);
+	quoted.push('"');
+
+	for c in value.chars() {
+		if c == '"' || c == '\\' {
+			quoted.push('\\');
+		}
+
+		quoted.push(c);
+	}
+
+	quoted.push('"');
+	quoted
+}
+
+enum Token {
+	Word(String),
+	Pair(String, String),
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>> {
+	let mut tokens = Vec::new();
+	let mut chars = line.chars().peekable();
+
+	loop {
+		while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+
+		if chars.peek().is_none() {
+			break;
+		}
+
+		let mut key = String::new();
+
+		while let Some(c) = chars.next_if(|c| *c != ' ' && *c != '\t' && *c != '=') {
+			if c == '"' {
+				return Err(protocol_error!("unexpected quote in SAM key: {}", line));
+			}
+
+			key.push(c);
+		}
+
+		if chars.next_if_eq(&'=').is_none() {
+			tokens.push(Token::Word(key));
+			continue;
+		}
+
+		if key.is_empty() {
+			return Err(protocol_error!("SAM pair without a key: {}", line));
+		}
+
+		let mut value = String::new();
+
+		if chars.next_if_eq(&'"').is_some() {
+			loop {
+				match chars.next() {
+					Some('"') => break,
+					Some('\\') => value.push(
+						chars
+							.next()
+							.ok_or_else(|| protocol_error!("unterminated escape in SAM message: {}", line))?,
+					),
+					Some(c) => value.push(c),
+					None => return Err(protocol_error!("unterminated quote in SAM message: {}", line)),
+				}
+			}
+
+			if chars.peek().is_some_and(|c| *c != ' ' && *c != '\t') {
+				return Err(protocol_error!("unexpected characters after quoted SAM value: {}", line));
+			}
+		} else {
+			while let Some(c) = chars.next_if(|c| *c != ' ' && *c != '\t') {
+				value.push(c);
+			}
+		}
+
+		tokens.push(Token::Pair(key, value));
+	}
+
+	Ok(tokens)
+}
@@ -0,0 +1,55 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+
+/// Prefix SAM 3.2 bridges put on the options of a name in NAMING REPLY.
+const OPTION_PREFIX: &str = "OPTION:";
+
+/// The answer to a NAMING LOOKUP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingLookup {
+	/// The name as the bridge reported it.
+	pub name: String,
+	pub destination: Destination,
+	/// Properties of the name, such as those in its address book entry, when they were asked for with OPTIONS=true.
+	pub options: BTreeMap<String, String>,
+}
+
+impl NamingLookup {
+	/// Reads a successful NAMING REPLY to a lookup of `name`, refusing one that answers for another name.
+	pub fn from_reply(name: &str, reply: &SamMessage) -> Result<Self> {
+		let replied = reply.get("NAME").unwrap_or(name);
+
+		if !replied.eq_ignore_ascii_case(name) {
+			return Err(protocol_error!("looked up {}, but SAM replied for {}", name, replied));
+		}
+
+		let destination = Destination::from_base64(reply.require("VALUE")?)?;
+
+		let options = reply
+			.pairs
+			.iter()
+			.filter_map(|(key, value)| key.strip_prefix(OPTION_PREFIX).map(|key| (key.to_owned(), value.clone())))
+			.collect();
+
+		Ok(Self {
+			name: replied.to_owned(),
+			destination,
+			options,
+		})
+	}
+
+	/// The NAMING REPLY a bridge answers a lookup with.
+	pub fn to_reply(&self) -> SamMessage {
+		let mut reply = SamMessage::new("NAMING", "REPLY")
+			.with("RESULT", "OK")
+			.with("NAME", &self.name)
+			.with("VALUE", &self.destination);
+
+		for (key, value) in &self.options {
+			reply.push(format!("{}{}", OPTION_PREFIX, key), value);
+		}
+
+		reply
+	}
+}
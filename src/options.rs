@@ -0,0 +1,115 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+
+/// I2CP and streaming options sent with SESSION CREATE.
+///
+/// Unset fields are left to the router's defaults. Shorter tunnels and fewer of them trade anonymity for latency and
+/// bandwidth; `extra` carries any option without a field, and wins over a field setting the same key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionOptions {
+	/// `inbound.length`: hops in each inbound tunnel.
+	pub inbound_length: Option<u8>,
+	/// `outbound.length`: hops in each outbound tunnel.
+	pub outbound_length: Option<u8>,
+	/// `inbound.lengthVariance`: random hops added to, or if negative also removed from, inbound tunnels.
+	pub inbound_length_variance: Option<i8>,
+	/// `outbound.lengthVariance`.
+	pub outbound_length_variance: Option<i8>,
+	/// `inbound.quantity`: inbound tunnels kept open.
+	pub inbound_quantity: Option<u8>,
+	/// `outbound.quantity`: outbound tunnels kept open.
+	pub outbound_quantity: Option<u8>,
+	/// `inbound.backupQuantity`.
+	pub inbound_backup_quantity: Option<u8>,
+	/// `outbound.backupQuantity`.
+	pub outbound_backup_quantity: Option<u8>,
+	/// `inbound.nickname`: how the router's console labels the tunnels.
+	pub inbound_nickname: Option<String>,
+	/// `outbound.nickname`.
+	pub outbound_nickname: Option<String>,
+	/// `i2cp.leaseSetEncType`: encryption types the leaseset offers, most preferred first.
+	pub lease_set_encryption_types: Vec<EncryptionType>,
+	/// `i2cp.dontPublishLeaseSet`: keeps the session unreachable, for clients that only connect out.
+	pub dont_publish_lease_set: Option<bool>,
+	/// `i2cp.reduceOnIdle`: shrinks the tunnel pools while the session is idle.
+	pub reduce_on_idle: Option<bool>,
+	/// `i2cp.reduceIdleTime`.
+	pub reduce_idle_time: Option<Duration>,
+	/// `i2cp.closeOnIdle`: closes the tunnels while the session is idle.
+	pub close_on_idle: Option<bool>,
+	/// `i2cp.closeIdleTime`.
+	pub close_idle_time: Option<Duration>,
+	/// `i2p.streaming.connectDelay`: how long a new stream waits for data to send along with its SYN.
+	pub streaming_connect_delay: Option<Duration>,
+	/// `i2p.streaming.maxWindowSize`.
+	pub streaming_max_window_size: Option<u32>,
+	/// Publishes the leaseset encrypted; see [`EncryptedLeaseSet`].
+	pub encrypted_lease_set: Option<EncryptedLeaseSet>,
+	/// Any other option, by its I2CP name.
+	pub extra: BTreeMap<String, String>,
+}
+
+impl SessionOptions {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a free-form option.
+	pub fn with<K: Into<String>, V: ToString>(mut self, key: K, value: V) -> Self {
+		self.extra.insert(key.into(), value.to_string());
+		self
+	}
+
+	/// The options that are set, as SESSION CREATE carries them.
+	pub fn options(&self) -> Vec<(String, String)> {
+		let milliseconds = |duration: Option<Duration>| duration.map(|duration| duration.as_millis().to_string());
+
+		let encryption_types = (!self.lease_set_encryption_types.is_empty()).then(|| {
+			self.lease_set_encryption_types
+				.iter()
+				.map(|encryption_type| encryption_type.code().to_string())
+				.collect::<Vec<_>>()
+				.join(",")
+		});
+
+		let fields = [
+			("inbound.length", text(self.inbound_length)),
+			("outbound.length", text(self.outbound_length)),
+			("inbound.lengthVariance", text(self.inbound_length_variance)),
+			("outbound.lengthVariance", text(self.outbound_length_variance)),
+			("inbound.quantity", text(self.inbound_quantity)),
+			("outbound.quantity", text(self.outbound_quantity)),
+			("inbound.backupQuantity", text(self.inbound_backup_quantity)),
+			("outbound.backupQuantity", text(self.outbound_backup_quantity)),
+			("inbound.nickname", self.inbound_nickname.clone()),
+			("outbound.nickname", self.outbound_nickname.clone()),
+			("i2cp.leaseSetEncType", encryption_types),
+			("i2cp.dontPublishLeaseSet", text(self.dont_publish_lease_set)),
+			("i2cp.reduceOnIdle", text(self.reduce_on_idle)),
+			("i2cp.reduceIdleTime", milliseconds(self.reduce_idle_time)),
+			("i2cp.closeOnIdle", text(self.close_on_idle)),
+			("i2cp.closeIdleTime", milliseconds(self.close_idle_time)),
+			("i2p.streaming.connectDelay", milliseconds(self.streaming_connect_delay)),
+			("i2p.streaming.maxWindowSize", text(self.streaming_max_window_size)),
+		];
+
+		let mut options: Vec<(String, String)> = fields
+			.into_iter()
+			.filter_map(|(key, value)| value.map(|value| (key.to_owned(), value)))
+			.collect();
+
+		if let Some(encrypted_lease_set) = &self.encrypted_lease_set {
+			options.extend(encrypted_lease_set.options());
+		}
+
+		options.retain(|(key, _)| !self.extra.contains_key(key));
+		options.extend(self.extra.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+		options
+	}
+}
+
+fn text<T: ToString>(value: Option<T>) -> Option<String> {
+	value.map(|value| value.to_string())
+}
@@ -0,0 +1,112 @@
+use crate::*;
+
+/// A SAM 3.3 PRIMARY session, which owns a destination and tunnel pool for its subsessions to share.
+///
+/// Subsessions are ordinary [`Session`]s: streams, datagram sockets and raw sockets all work on them. Each is added
+/// to the bridge with SESSION ADD the first time it is used, and removed with SESSION REMOVE when it is closed.
+#[derive(Debug)]
+pub struct PrimarySession {
+	control: Arc<Mutex<SamConnection>>,
+	config: SamConfig,
+	pub public_key: Destination,
+	pub private_key: PrivateKeys,
+	pub service: String,
+}
+
+/// What sets a subsession apart from its siblings on the same destination.
+#[derive(Debug, Clone)]
+pub(crate) struct Subsession {
+	pub(crate) listen_port: u16,
+}
+
+impl PrimarySession {
+	/// Creates a primary session with a freshly generated destination.
+	pub async fn new<S: Into<String>>(service: S) -> Result<Self> {
+		Self::builder(service).build_primary().await
+	}
+
+	/// Restores a primary session from a previously generated destination.
+	pub async fn from<S: Into<String>>(service: S, private_keys: PrivateKeys) -> Result<Self> {
+		Self::builder(service).keys(private_keys).build_primary().await
+	}
+
+	/// A builder whose [`SessionBuilder::build_primary`] creates a primary session.
+	pub fn builder<S: Into<String>>(service: S) -> SessionBuilder {
+		SessionBuilder::new(service, SessionStyle::Stream)
+	}
+
+	/// Does HELLO, DEST GENERATE unless `private_keys` are given, and SESSION CREATE STYLE=PRIMARY.
+	pub(crate) async fn create(
+		service: String,
+		config: SamConfig,
+		private_keys: Option<PrivateKeys>,
+		signature_type: Option<SignatureType>,
+		options: SessionOptions,
+	) -> Result<Self> {
+		trace!("creating new primary session with id {} on {}", service, config.sam_address);
+
+		let mut connection = SamConnection::connect(&service, &config).await?;
+
+		if !connection.supports(3, 3) {
+			return Err(protocol_error!("primary sessions need a SAM 3.3 bridge"));
+		}
+
+		let private_keys = match private_keys {
+			Some(private_keys) => private_keys,
+			None => generate_keys(&service, &mut connection, signature_type).await?,
+		};
+
+		let mut session_create = SamMessage::new("SESSION", "CREATE")
+			.with("STYLE", "PRIMARY")
+			.with("ID", &service)
+			.with("DESTINATION", private_keys.to_base64());
+
+		for (key, value) in options.options() {
+			session_create.push(key, value);
+		}
+
+		connection.command(&session_create).await?;
+
+		Ok(Self {
+			control: Arc::new(Mutex::new(connection)),
+			config,
+			public_key: private_keys.destination().clone(),
+			private_key: private_keys,
+			service,
+		})
+	}
+
+	/// Adds a subsession accepting streams or datagrams sent to `listen_port`, or to any port if it is 0.
+	///
+	/// `service` is the subsession's own ID, and must differ from the primary's and its siblings'. Nothing is sent
+	/// to the bridge until the subsession is first used.
+	pub fn add<S: Into<String>>(&self, service: S, session_style: SessionStyle, listen_port: u16) -> Session {
+		Session {
+			control: self.control.clone(),
+			created: AtomicBool::new(false),
+			subsession: Some(Subsession { listen_port }),
+			config: self.config.clone(),
+			session_style,
+			forwarders: Vec::new(),
+			options: SessionOptions::default(),
+			public_key: self.public_key.clone(),
+			private_key: self.private_key.clone(),
+			service: service.into(),
+		}
+	}
+
+	pub fn config(&self) -> &SamConfig {
+		&self.config
+	}
+
+	pub fn address(&self) -> Result<String> {
+		Ok(self.public_key.b32_address().to_string())
+	}
+
+	/// Closes the control connection, which ends the primary session and every subsession on the bridge.
+	pub async fn close(self) -> Result<()> {
+		debug!("sam connection with ID {} is closing its primary session", self.service);
+
+		self.control.lock().await.shutdown().await
+	}
+}
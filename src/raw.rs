@@ -0,0 +1,151 @@
+use crate::datagram::{parse_header_value, ForwardedSocket};
+use crate::*;
+
+/// An anonymous datagram as the bridge forwards it to a raw session.
+///
+/// Raw datagrams carry no sender destination. With HEADER=true the bridge puts a line holding FROM_PORT, TO_PORT
+/// and PROTOCOL before the payload; without it the payload is all there is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RawDatagramMessage {
+	pub from_port: Option<u16>,
+	pub to_port: Option<u16>,
+	pub protocol: Option<u8>,
+	pub contents: Vec<u8>,
+}
+
+impl RawDatagramMessage {
+	pub fn new(contents: Vec<u8>) -> Self {
+		Self {
+			contents,
+			..Self::default()
+		}
+	}
+
+	fn has_header(&self) -> bool {
+		self.from_port.is_some() || self.to_port.is_some() || self.protocol.is_some()
+	}
+
+	/// Serializes the datagram the way the bridge delivers it, with a header line only if a port or protocol is set.
+	pub fn serialize(&self) -> Vec<u8> {
+		debug!("serializing raw datagram message");
+
+		let mut bytes = Vec::with_capacity(self.contents.len() + 48);
+
+		if self.has_header() {
+			let fields = [
+				("FROM_PORT", self.from_port.map(u32::from)),
+				("TO_PORT", self.to_port.map(u32::from)),
+				("PROTOCOL", self.protocol.map(u32::from)),
+			];
+
+			let header: Vec<String> = fields
+				.iter()
+				.filter_map(|(key, value)| value.map(|value| format!("{}={}", key, value)))
+				.collect();
+
+			bytes.extend_from_slice(header.join(" ").as_bytes());
+			bytes.push(b'\n');
+		}
+
+		bytes.extend_from_slice(&self.contents);
+
+		bytes
+	}
+
+	/// Parses a datagram received by a raw session, which was created with HEADER=true if `header` is set.
+	pub fn from_bytes(buffer: &[u8], header: bool) -> Result<Self> {
+		debug!("deserializing raw datagram message");
+
+		if !header {
+			return Ok(Self::new(buffer.to_vec()));
+		}
+
+		let split_buffer: Vec<&[u8]> = buffer.splitn(2, |byte| *byte == b'\n').collect();
+
+		let contents = split_buffer
+			.get(1)
+			.ok_or_else(|| protocol_error!("could not find contents of raw datagram message"))?
+			.to_vec();
+
+		let mut message = Self::new(contents);
+
+		for (key, value) in SamMessage::parse_pairs(&String::from_utf8(split_buffer[0].to_vec())?)? {
+			match key.as_str() {
+				"FROM_PORT" => message.from_port = Some(parse_header_value(&key, &value)?),
+				"TO_PORT" => message.to_port = Some(parse_header_value(&key, &value)?),
+				"PROTOCOL" => message.protocol = Some(parse_header_value(&key, &value)?),
+				_ => trace!("ignoring raw datagram header {}={}", key, value),
+			}
+		}
+
+		Ok(message)
+	}
+}
+
+/// A UDP-like socket for anonymous, unrepliable datagrams over I2P.
+///
+/// Received datagrams carry no sender; when the socket is bound with a header, they carry ports and protocol.
+#[derive(Debug)]
+pub struct RawDatagramSocket {
+	socket: ForwardedSocket,
+	session: Session,
+	header: bool,
+}
+
+impl RawDatagramSocket {
+	/// Creates a raw session with a new destination and binds a socket to it.
+	pub async fn bind<S: Into<String>>(service: S) -> Result<Self> {
+		Self::from_session(Session::new(service, SessionStyle::Raw).await?, false).await
+	}
+
+	/// Like [`RawDatagramSocket::bind`], but received datagrams report their ports and protocol.
+	pub async fn bind_with_header<S: Into<String>>(service: S) -> Result<Self> {
+		Self::from_session(Session::new(service, SessionStyle::Raw).await?, true).await
+	}
+
+	/// Binds a socket to a raw session that has not been forwarded yet.
+	pub async fn from_session(mut session: Session, header: bool) -> Result<Self> {
+		if session.session_style != SessionStyle::Raw {
+			return Err(protocol_error!("raw datagram sockets need a raw session"));
+		}
+
+		let socket = ForwardedSocket::bind(&mut session, header).await?;
+
+		Ok(Self { socket, session, header })
+	}
+
+	/// Sends `buffer` as one raw datagram to `destination`, returning the number of payload bytes sent.
+	pub async fn send_to(&self, buffer: &[u8], destination: &Destination) -> Result<usize> {
+		let datagram = DatagramMessage::new(self.session.service.clone(), destination.to_base64(), buffer.to_vec());
+
+		self.socket.send(&datagram.serialize()).await?;
+
+		Ok(buffer.len())
+	}
+
+	/// Sends a raw datagram with its SAM 3.2 options, such as ports and protocol; its service is replaced with this
+	/// socket's.
+	pub async fn send(&self, mut datagram: DatagramMessage) -> Result<usize> {
+		datagram.service = self.session.service.clone();
+
+		self.socket.send(&datagram.serialize()).await?;
+
+		Ok(datagram.contents.len())
+	}
+
+	/// Waits for the next raw datagram.
+	pub async fn recv(&self) -> Result<RawDatagramMessage> {
+		let buffer = self.socket.recv().await?;
+
+		RawDatagramMessage::from_bytes(&buffer, self.header)
+	}
+
+	/// The destination other peers send datagrams to.
+	pub fn local_destination(&self) -> &Destination {
+		&self.session.public_key
+	}
+
+	pub fn session(&self) -> &Session {
+		&self.session
+	}
+}
@@ -0,0 +1,306 @@
+use crate::*;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex as StdMutex, RwLock};
+use std::time::Instant;
+
+use tokio::sync::OnceCell;
+
+/// What a [`Resolver`] finds: the destination, or `None` if it doesn't know the name.
+pub type Resolution<'a> = Pin<Box<dyn Future<Output = Result<Option<Destination>>> + Send + 'a>>;
+
+/// Turns hostnames and b32 addresses into destinations.
+///
+/// Address books, sessions and caches are resolvers, and a [`ResolverChain`] asks several in turn.
+pub trait Resolver: Send + Sync {
+	/// Resolves `name`, or returns `None` so that the next resolver in a chain is asked.
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a>;
+}
+
+impl Resolver for AddressBook {
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a> {
+		let destination = self.look_up(name).cloned();
+
+		Box::pin(async move { Ok(destination) })
+	}
+}
+
+/// An address book that can be updated while resolvers share it.
+impl Resolver for RwLock<AddressBook> {
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a> {
+		let destination = self.read().unwrap().look_up(name).cloned();
+
+		Box::pin(async move { Ok(destination) })
+	}
+}
+
+/// Asks the router with NAMING LOOKUP.
+impl Resolver for Session {
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a> {
+		Box::pin(async move {
+			match self.look_up(name).await {
+				Ok(destination) => Ok(Some(destination)),
+				Err(error) if error.is_not_found() => Ok(None),
+				Err(error) => Err(error),
+			}
+		})
+	}
+}
+
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a> {
+		(**self).resolve(name)
+	}
+}
+
+/// Asks resolvers in the order they were added until one knows the name, such as a local address book, then a cache
+/// in front of the router.
+///
+/// An error from any of them ends the lookup.
+#[derive(Default)]
+pub struct ResolverChain {
+	resolvers: Vec<Box<dyn Resolver>>,
+}
+
+impl ResolverChain {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a resolver, asked after the ones already in the chain.
+	pub fn then<R: Resolver + 'static>(mut self, resolver: R) -> Self {
+		self.resolvers.push(Box::new(resolver));
+		self
+	}
+}
+
+impl std::fmt::Debug for ResolverChain {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		formatter
+			.debug_struct("ResolverChain")
+			.field("resolvers", &self.resolvers.len())
+			.finish()
+	}
+}
+
+impl Resolver for ResolverChain {
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a> {
+		Box::pin(async move {
+			for resolver in &self.resolvers {
+				if let Some(destination) = resolver.resolve(name).await? {
+					return Ok(Some(destination));
+				}
+			}
+
+			Ok(None)
+		})
+	}
+}
+
+/// How long a [`CachedResolver`] remembers a destination by default.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How long a [`CachedResolver`] remembers by default that a name was not found.
+pub const DEFAULT_NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// How many names and b32 addresses a [`CachedResolver`] remembers by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// Remembers what another resolver found, by name and by b32 address, so that busy names don't cost a lookup each.
+///
+/// Destinations are kept for [`CachedResolver::ttl`], and names that were not found for
+/// [`CachedResolver::negative_ttl`]. Concurrent lookups of a name wait for the same lookup of the inner resolver, so
+/// only one NAMING LOOKUP is sent for them. Errors are not remembered: a waiter whose lookup failed tries again itself.
+///
+/// At most [`CachedResolver::capacity`] names are kept. Once it is reached, expired entries are dropped first, then the
+/// ones closest to expiring.
+#[derive(Debug)]
+pub struct CachedResolver<R> {
+	resolver: R,
+	ttl: Duration,
+	negative_ttl: Duration,
+	capacity: usize,
+	cache: StdMutex<HashMap<String, Arc<CacheEntry>>>,
+}
+
+/// A lookup, in progress until its cell is set.
+#[derive(Debug, Default)]
+struct CacheEntry {
+	resolved: OnceCell<(Option<Destination>, Instant)>,
+}
+
+impl CacheEntry {
+	fn resolved(destination: Option<Destination>, expires: Instant) -> Self {
+		Self {
+			resolved: OnceCell::new_with(Some((destination, expires))),
+		}
+	}
+
+	fn is_expired(&self, now: Instant) -> bool {
+		self.expires().is_some_and(|expires| expires <= now)
+	}
+
+	/// When the entry expires, or `None` while its lookup is in progress.
+	fn expires(&self) -> Option<Instant> {
+		self.resolved.get().map(|(_, expires)| *expires)
+	}
+
+	fn destination(&self) -> Option<&Destination> {
+		self.resolved.get().and_then(|(destination, _)| destination.as_ref())
+	}
+}
+
+impl<R: Resolver> CachedResolver<R> {
+	pub fn new(resolver: R) -> Self {
+		Self {
+			resolver,
+			ttl: DEFAULT_CACHE_TTL,
+			negative_ttl: DEFAULT_NEGATIVE_CACHE_TTL,
+			capacity: DEFAULT_CACHE_CAPACITY,
+			cache: StdMutex::new(HashMap::new()),
+		}
+	}
+
+	/// Sets how long destinations are remembered.
+	pub fn ttl(mut self, ttl: Duration) -> Self {
+		self.ttl = ttl;
+		self
+	}
+
+	/// Sets how long names that were not found are remembered; zero turns negative caching off.
+	pub fn negative_ttl(mut self, negative_ttl: Duration) -> Self {
+		self.negative_ttl = negative_ttl;
+		self
+	}
+
+	/// Sets how many names and b32 addresses are remembered.
+	pub fn capacity(mut self, capacity: usize) -> Self {
+		self.capacity = capacity;
+		self
+	}
+
+	/// Drops what was remembered about `name`, and about the b32 address it resolved to, so the next lookup asks again.
+	pub fn forget(&self, name: &str) {
+		let mut cache = self.cache();
+
+		let Some(entry) = cache.remove(&name.to_ascii_lowercase()) else {
+			return;
+		};
+
+		if let Some(destination) = entry.destination() {
+			let address = B32Address::from(destination).to_string();
+
+			if cache.get(&address).is_some_and(|alias| alias.destination() == Some(destination)) {
+				cache.remove(&address);
+			}
+		}
+	}
+
+	pub fn clear(&self) {
+		self.cache().clear();
+	}
+
+	/// How many names and b32 addresses are remembered, or being looked up.
+	pub fn len(&self) -> usize {
+		self.cache().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.cache().is_empty()
+	}
+
+	/// Drops every expired entry. Expired entries are otherwise only replaced when their name is looked up again.
+	pub fn purge_expired(&self) {
+		let now = Instant::now();
+
+		self.cache().retain(|_, entry| !entry.is_expired(now));
+	}
+
+	fn cache(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<CacheEntry>>> {
+		self.cache.lock().unwrap()
+	}
+
+	/// The entry for `key`, replacing it with a new lookup if it has expired.
+	fn entry(&self, key: &str) -> Arc<CacheEntry> {
+		let mut cache = self.cache();
+
+		match cache.get(key) {
+			Some(entry) if !entry.is_expired(Instant::now()) => entry.clone(),
+			_ => {
+				let entry = Arc::new(CacheEntry::default());
+				self.insert(&mut cache, key.to_owned(), entry.clone());
+				entry
+			}
+		}
+	}
+
+	/// Inserts an entry, first making room for it if the cache is full.
+	fn insert(&self, cache: &mut HashMap<String, Arc<CacheEntry>>, key: String, entry: Arc<CacheEntry>) {
+		if !cache.contains_key(&key) && cache.len() >= self.capacity {
+			let now = Instant::now();
+			cache.retain(|_, entry| !entry.is_expired(now));
+
+			// Lookups in progress have waiters, so only finished ones are given up.
+			let soonest = cache
+				.iter()
+				.filter_map(|(key, entry)| Some((entry.expires()?, key)))
+				.min()
+				.map(|(_, key)| key.clone());
+
+			if let Some(soonest) = soonest.filter(|_| cache.len() >= self.capacity) {
+				cache.remove(&soonest);
+			}
+		}
+
+		cache.insert(key, entry);
+	}
+
+	/// Drops the entry of a lookup that failed, unless it has been replaced or another waiter has since succeeded.
+	fn remove_failed(&self, key: &str, failed: &Arc<CacheEntry>) {
+		let mut cache = self.cache();
+
+		if cache
+			.get(key)
+			.is_some_and(|entry| Arc::ptr_eq(entry, failed) && !entry.resolved.initialized())
+		{
+			cache.remove(key);
+		}
+	}
+
+	async fn look_up(&self, name: &str) -> Result<(Option<Destination>, Instant)> {
+		let destination = self.resolver.resolve(name).await?;
+
+		let ttl = if destination.is_some() { self.ttl } else { self.negative_ttl };
+		let expires = Instant::now() + ttl;
+
+		if let Some(destination) = &destination {
+			let address = B32Address::from(destination).to_string();
+
+			if address != name.to_ascii_lowercase() {
+				let alias = Arc::new(CacheEntry::resolved(Some(destination.clone()), expires));
+				self.insert(&mut self.cache(), address, alias);
+			}
+		}
+
+		Ok((destination, expires))
+	}
+}
+
+impl<R: Resolver> Resolver for CachedResolver<R> {
+	fn resolve<'a>(&'a self, name: &'a str) -> Resolution<'a> {
+		Box::pin(async move {
+			let key = name.to_ascii_lowercase();
+			let entry = self.entry(&key);
+
+			match entry.resolved.get_or_try_init(|| self.look_up(name)).await {
+				Ok((destination, _)) => Ok(destination.clone()),
+				Err(error) => {
+					self.remove_failed(&key, &entry);
+					Err(error)
+				}
+			}
+		})
+	}
+}
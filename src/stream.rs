@@ -1,28 +1,220 @@
 use crate::*;
 use tokio::io::AsyncBufReadExt;
 
-// TODO: add FROM_PORT and TO_PORT
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, BufReader, ReadBuf};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+
+/// The header line the bridge sends ahead of an incoming stream when SILENT=false.
+///
+/// It names the peer's destination and, from SAM 3.2 on, the ports the stream was sent from and to.
+#[derive(Debug, Clone, PartialEq)]
 pub struct StreamInfo {
 	pub destination: String,
+	pub from_port: Option<u16>,
+	pub to_port: Option<u16>,
 }
 
 impl StreamInfo {
+	pub fn new<S: Into<String>>(destination: S) -> Self {
+		Self {
+			destination: destination.into(),
+			from_port: None,
+			to_port: None,
+		}
+	}
+
 	pub async fn from_bufread<T: tokio::io::AsyncBufRead + std::marker::Unpin>(stream: &mut T) -> Result<Self> {
 		debug!("deserializing stream info");
 
 		let mut header = String::new();
 		stream.read_line(&mut header).await?;
 
-		let expression = regex::Regex::new("^[^ ]+")?;
+		let header = header.trim_end_matches(['\n', '\r']);
+		let (destination, options) = header.split_once(' ').unwrap_or((header, ""));
+
+		if destination.is_empty() {
+			return Err(protocol_error!("Could not find destination in header"));
+		}
+
+		let mut stream_info = Self::new(destination);
+
+		for (key, value) in SamMessage::parse_pairs(options)? {
+			let port = || {
+				value
+					.parse()
+					.map_err(|_| protocol_error!("invalid {} in stream header: {}", key, value))
+			};
+
+			match key.as_str() {
+				"FROM_PORT" => stream_info.from_port = Some(port()?),
+				"TO_PORT" => stream_info.to_port = Some(port()?),
+				_ => trace!("ignoring stream header {}={}", key, value),
+			}
+		}
+
+		Ok(stream_info)
+	}
+
+	/// Writes the header as the bridge would, newline included.
+	pub fn serialize(&self) -> String {
+		let mut header = self.destination.clone();
+
+		if let Some(from_port) = self.from_port {
+			header.push_str(&format!(" FROM_PORT={}", from_port));
+		}
+
+		if let Some(to_port) = self.to_port {
+			header.push_str(&format!(" TO_PORT={}", to_port));
+		}
+
+		header.push('\n');
+		header
+	}
+}
+
+/// A stream to another I2P destination, carried over its own connection to the SAM bridge.
+///
+/// Returned by [`Session::connect_stream`] and [`StreamListener::accept`].
+#[derive(Debug)]
+pub struct I2pStream {
+	stream: TcpStream,
+	/// Data that arrived right behind the SAM handshake and was already buffered.
+	buffered: Vec<u8>,
+	local_destination: Destination,
+	peer_destination: Destination,
+	local_port: u16,
+	peer_port: u16,
+}
+
+impl I2pStream {
+	pub(crate) fn new(
+		reader: BufReader<TcpStream>,
+		local_destination: Destination,
+		peer_destination: Destination,
+		local_port: u16,
+		peer_port: u16,
+	) -> Self {
+		let buffered = reader.buffer().to_vec();
+
+		Self {
+			stream: reader.into_inner(),
+			buffered,
+			local_destination,
+			peer_destination,
+			local_port,
+			peer_port,
+		}
+	}
+
+	/// The destination of the session this stream belongs to.
+	pub fn local_destination(&self) -> &Destination {
+		&self.local_destination
+	}
+
+	/// The destination on the other end of the stream.
+	pub fn peer_destination(&self) -> &Destination {
+		&self.peer_destination
+	}
+
+	/// The I2P port on our side, or 0 if none was given.
+	pub fn local_port(&self) -> u16 {
+		self.local_port
+	}
+
+	/// The I2P port on the peer's side, or 0 if none was given.
+	pub fn peer_port(&self) -> u16 {
+		self.peer_port
+	}
+
+	/// Splits the stream into halves that can be used from different tasks.
+	pub fn into_split(self) -> (I2pReadHalf, I2pWriteHalf) {
+		let (read_half, write_half) = self.stream.into_split();
 
-		let destination = expression
-			.captures(&header)
-			.context("Could not regex header")?
-			.get(0)
-			.context("Could not find destination in header")?
-			.as_str()
-			.to_owned();
+		(
+			I2pReadHalf {
+				stream: read_half,
+				buffered: self.buffered,
+			},
+			I2pWriteHalf { stream: write_half },
+		)
+	}
+}
+
+impl AsyncRead for I2pStream {
+	fn poll_read(mut self: Pin<&mut Self>, context: &mut Context<'_>, buffer: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+		let this = &mut *self;
+
+		poll_read_buffered(&mut this.buffered, Pin::new(&mut this.stream), context, buffer)
+	}
+}
+
+impl AsyncWrite for I2pStream {
+	fn poll_write(mut self: Pin<&mut Self>, context: &mut Context<'_>, buffer: &[u8]) -> Poll<std::io::Result<usize>> {
+		Pin::new(&mut self.stream).poll_write(context, buffer)
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Pin::new(&mut self.stream).poll_flush(context)
+	}
+
+	fn poll_shutdown(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Pin::new(&mut self.stream).poll_shutdown(context)
+	}
+}
+
+/// The reading half of an [`I2pStream`], created by [`I2pStream::into_split`].
+#[derive(Debug)]
+pub struct I2pReadHalf {
+	stream: OwnedReadHalf,
+	buffered: Vec<u8>,
+}
 
-		Ok(Self { destination })
+impl AsyncRead for I2pReadHalf {
+	fn poll_read(mut self: Pin<&mut Self>, context: &mut Context<'_>, buffer: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+		let this = &mut *self;
+
+		poll_read_buffered(&mut this.buffered, Pin::new(&mut this.stream), context, buffer)
+	}
+}
+
+/// The writing half of an [`I2pStream`], created by [`I2pStream::into_split`].
+#[derive(Debug)]
+pub struct I2pWriteHalf {
+	stream: OwnedWriteHalf,
+}
+
+impl AsyncWrite for I2pWriteHalf {
+	fn poll_write(mut self: Pin<&mut Self>, context: &mut Context<'_>, buffer: &[u8]) -> Poll<std::io::Result<usize>> {
+		Pin::new(&mut self.stream).poll_write(context, buffer)
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Pin::new(&mut self.stream).poll_flush(context)
 	}
+
+	fn poll_shutdown(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		Pin::new(&mut self.stream).poll_shutdown(context)
+	}
+}
+
+/// Hands out `buffered` before reading from `stream`.
+fn poll_read_buffered<R: AsyncRead>(
+	buffered: &mut Vec<u8>,
+	stream: Pin<&mut R>,
+	context: &mut Context<'_>,
+	buffer: &mut ReadBuf<'_>,
+) -> Poll<std::io::Result<()>> {
+	if buffered.is_empty() {
+		return stream.poll_read(context, buffer);
+	}
+
+	let length = buffered.len().min(buffer.remaining());
+	buffer.put_slice(&buffered[..length]);
+	buffered.drain(..length);
+
+	Poll::Ready(Ok(()))
 }
@@ -0,0 +1,102 @@
+//! Test support, enabled by the `testing` feature.
+
+use crate::bridge::{Bridge, Interception, Interceptor, LoopbackTransport};
+use crate::*;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex as StdMutex;
+
+/// An in-process SAM bridge on loopback, for testing code built on this crate without an I2P router.
+///
+/// A [`Bridge`] on its own [`LoopbackTransport`]: streams and datagrams are routed between the sessions created on
+/// it, and nothing leaves the process. Failures and latency can be injected per [`Operation`], and every command
+/// received is recorded.
+#[derive(Debug)]
+pub struct MockBridge {
+	bridge: Bridge,
+	transport: LoopbackTransport,
+	faults: Arc<Faults>,
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+	state: StdMutex<FaultState>,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+	failures: VecDeque<(Operation, SamResult)>,
+	delays: HashMap<Operation, Duration>,
+	commands: Vec<SamMessage>,
+}
+
+impl Interceptor for Faults {
+	fn intercept(&self, command: &SamMessage) -> Interception {
+		let mut state = self.state.lock().unwrap();
+		state.commands.push(command.clone());
+
+		let operation = Operation::of(command);
+		let failure = state.failures.iter().position(|(failing, _)| *failing == operation);
+
+		Interception {
+			delay: state.delays.get(&operation).copied(),
+			failure: failure.and_then(|index| state.failures.remove(index)).map(|(_, result)| result),
+		}
+	}
+}
+
+impl MockBridge {
+	/// Starts the bridge on ephemeral loopback ports.
+	pub async fn start() -> Result<Self> {
+		let transport = LoopbackTransport::new();
+		let faults = Arc::new(Faults::default());
+
+		let bridge = Bridge::builder(transport.clone()).interceptor(faults.clone()).start().await?;
+
+		Ok(Self { bridge, transport, faults })
+	}
+
+	/// Bridge configuration pointing at this mock.
+	pub fn config(&self) -> SamConfig {
+		self.bridge.config()
+	}
+
+	/// A session builder already pointing at this mock.
+	pub fn session_builder<S: Into<String>>(&self, service: S, session_style: SessionStyle) -> SessionBuilder {
+		Session::builder(service, session_style).config(self.config())
+	}
+
+	/// The network the mock's destinations live on, which other bridges can share.
+	pub fn transport(&self) -> &LoopbackTransport {
+		&self.transport
+	}
+
+	/// Makes NAMING LOOKUP resolve `name` to `destination`.
+	pub fn register_name<S: Into<String>>(&self, name: S, destination: Destination) {
+		self.transport.register_name(name, destination);
+	}
+
+	/// Like [`MockBridge::register_name`], with properties returned to lookups with OPTIONS=true.
+	pub fn register_name_with_options<S: Into<String>>(&self, name: S, destination: Destination, options: BTreeMap<String, String>) {
+		self.transport.register_name_with_options(name, destination, options);
+	}
+
+	/// Makes the next command of `operation` fail with `result`.
+	pub fn fail_next(&self, operation: Operation, result: SamResult) {
+		self.faults().failures.push_back((operation, result));
+	}
+
+	/// Delays every reply to commands of `operation`, until it is set to `Duration::ZERO` again.
+	pub fn delay(&self, operation: Operation, delay: Duration) {
+		self.faults().delays.insert(operation, delay);
+	}
+
+	/// Every command received so far, in order.
+	pub fn commands(&self) -> Vec<SamMessage> {
+		self.faults().commands.clone()
+	}
+
+	fn faults(&self) -> std::sync::MutexGuard<'_, FaultState> {
+		self.faults.state.lock().unwrap()
+	}
+}
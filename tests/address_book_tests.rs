@@ -0,0 +1,125 @@
+use solitude::{AddressBook, B32Address, Destination, HostEntry, PrivateKeys, SignatureType};
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+fn hosts_path(name: &str) -> PathBuf {
+	std::env::temp_dir().join(format!("solitude_{}_{}.txt", name, std::process::id()))
+}
+
+fn destination() -> Result<Destination> {
+	Ok(PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone())
+}
+
+#[test]
+fn hosts_files_are_parsed_with_their_properties() -> Result<()> {
+	let first = destination()?;
+	let second = destination()?;
+
+	let text = format!(
+		"# a comment\n\nExample.i2p={}#!date=1600000000#sig=abc\nother.i2p={}\n#!action=remove#name=example.i2p\n",
+		first, second
+	);
+	let address_book = AddressBook::parse(&text);
+
+	assert_eq!(address_book.len(), 2);
+
+	let entry = address_book.get("example.i2p").unwrap();
+	assert_eq!(entry.destination, first);
+	assert_eq!(entry.properties.get("date").map(String::as_str), Some("1600000000"));
+	assert_eq!(entry.properties.get("sig").map(String::as_str), Some("abc"));
+
+	assert_eq!(address_book.look_up("OTHER.i2p"), Some(&second));
+	assert_eq!(address_book.look_up(&B32Address::from(&second).to_string()), Some(&second));
+	assert_eq!(address_book.look_up("nowhere.i2p"), None);
+
+	Ok(())
+}
+
+#[test]
+fn invalid_lines_are_skipped() -> Result<()> {
+	let valid = destination()?;
+
+	let text = format!(
+		"valid.i2p={}\nno-destination.i2p\nbad.i2p=notbase64!\nnot a host.i2p={}\n{}.b32.i2p={}\n",
+		valid,
+		valid,
+		"a".repeat(52),
+		valid
+	);
+	let address_book = AddressBook::parse(&text);
+
+	assert_eq!(address_book.len(), 1);
+	assert_eq!(address_book.look_up("valid.i2p"), Some(&valid));
+
+	Ok(())
+}
+
+#[test]
+fn address_books_round_trip_through_files() -> Result<()> {
+	let path = hosts_path("address_books_round_trip_through_files");
+
+	let mut address_book = AddressBook::new();
+
+	let mut entry = HostEntry::new("example.i2p", destination()?);
+	entry.properties.insert("date".to_owned(), "1600000000".to_owned());
+
+	address_book.insert(entry);
+	address_book.insert(HostEntry::new("other.i2p", destination()?));
+
+	address_book.save(&path)?;
+	assert_eq!(AddressBook::load(&path)?, address_book);
+
+	std::fs::remove_file(&path)?;
+
+	Ok(())
+}
+
+#[test]
+fn subscriptions_keep_existing_names_and_ignore_removals() -> Result<()> {
+	let path = hosts_path("subscriptions_keep_existing_names_and_ignore_removals");
+
+	let mine = destination()?;
+	let kept = destination()?;
+	let theirs = destination()?;
+
+	let mut address_book = AddressBook::new();
+	address_book.insert(HostEntry::new("mine.i2p", mine.clone()));
+	address_book.insert(HostEntry::new("kept.i2p", kept.clone()));
+
+	let feed = format!(
+		"mine.i2p={}
+new.i2p={}
+#!action=remove#name=kept.i2p
+",
+		theirs, theirs
+	);
+	std::fs::write(&path, feed)?;
+
+	assert_eq!(address_book.subscribe(&path)?, 1);
+
+	assert_eq!(address_book.look_up("mine.i2p"), Some(&mine));
+	assert_eq!(address_book.look_up("new.i2p"), Some(&theirs));
+	assert_eq!(address_book.look_up("kept.i2p"), Some(&kept));
+
+	std::fs::remove_file(&path)?;
+
+	Ok(())
+}
+
+#[test]
+fn feeds_cannot_take_a_name_over_by_removing_it_first() -> Result<()> {
+	let bank = destination()?;
+	let evil = destination()?;
+
+	let mut address_book = AddressBook::new();
+	address_book.insert(HostEntry::new("bank.i2p", bank.clone()));
+
+	let added = address_book.merge(&format!("#!action=remove#name=bank.i2p\nbank.i2p={}\n", evil));
+
+	assert_eq!(added, 0);
+	assert_eq!(address_book.look_up("bank.i2p"), Some(&bank));
+
+	Ok(())
+}
@@ -0,0 +1,96 @@
+use solitude::{B32Address, B33Key, Error, PrivateKeys, SignatureType};
+
+use anyhow::Result;
+
+#[test]
+fn destination_address_round_trips() -> Result<()> {
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let destination = private_keys.destination();
+
+	let address = B32Address::from(destination);
+	let parsed: B32Address = address.to_string().parse()?;
+
+	assert_eq!(parsed, address);
+	assert_eq!(parsed.hash(), Some(destination.hash()));
+	assert!(parsed.matches(destination));
+	assert!(!parsed.is_blinded());
+	assert!(!parsed.matches(PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination()));
+
+	Ok(())
+}
+
+#[test]
+fn addresses_are_case_insensitive() -> Result<()> {
+	let address = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?
+		.destination()
+		.b32_address();
+
+	assert_eq!(address.to_string().to_uppercase().parse::<B32Address>()?, address);
+	assert_eq!(address.to_string(), address.to_string().to_lowercase());
+
+	Ok(())
+}
+
+#[test]
+fn encrypted_leaseset_addresses_round_trip() -> Result<()> {
+	let b33_key = B33Key {
+		signature_type: SignatureType::EdDsaSha512Ed25519,
+		blinded_signature_type: SignatureType::RedDsaSha512Ed25519,
+		signing_public_key: (0..32).collect(),
+		secret_required: true,
+		client_auth_required: false,
+	};
+
+	let address = B32Address::from(&b33_key);
+	let parsed: B32Address = address.to_string().parse()?;
+
+	assert_eq!(address.to_string().len(), 56 + ".b32.i2p".len());
+	assert!(parsed.is_blinded());
+	assert_eq!(parsed.hash(), None);
+	assert_eq!(parsed.b33_key(), Some(b33_key));
+
+	Ok(())
+}
+
+#[test]
+fn rejects_corrupted_encrypted_leaseset_addresses() {
+	let b33_key = B33Key {
+		signature_type: SignatureType::EdDsaSha512Ed25519,
+		blinded_signature_type: SignatureType::RedDsaSha512Ed25519,
+		signing_public_key: vec![7; 32],
+		secret_required: false,
+		client_auth_required: true,
+	};
+
+	let address = B32Address::from(&b33_key).to_string();
+
+	// Changing the key changes the CRC that masks the flags and signature types.
+	let corrupted = format!("{}{}", &address[..20], if &address[20..21] == "a" { "b" } else { "a" }) + &address[21..];
+
+	assert!(matches!(corrupted.parse::<B32Address>(), Err(Error::Address(_))));
+	assert!(matches!(
+		format!("{}.b32.i2p", "a".repeat(56)).parse::<B32Address>(),
+		Err(Error::Address(_))
+	));
+}
+
+#[test]
+fn rejects_malformed_addresses() {
+	for address in [
+		format!("{}.b32.i2p", "a".repeat(51)),
+		format!("{}.b32.i2p", "a".repeat(53)),
+		format!("{}.b32.i2p", "a".repeat(55)),
+		format!("{}b.b32.i2p", "a".repeat(51)),
+		format!("{}1.b32.i2p", "a".repeat(51)),
+		format!("{}.i2p", "a".repeat(52)),
+		format!("{}.b32.i2p.com", "a".repeat(52)),
+		"example.i2p".to_owned(),
+		String::new(),
+	] {
+		assert!(
+			matches!(address.parse::<B32Address>(), Err(Error::Address(_))),
+			"{:?} should not parse",
+			address
+		);
+	}
+}
@@ -0,0 +1,273 @@
+use solitude::bridge::{Bridge, LoopbackTransport};
+use solitude::{PrivateKeys, SamMessage, Session, SessionStyle};
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Sends one line the way a client in any language would, and reads the bridge's reply.
+async fn exchange(connection: &mut BufReader<TcpStream>, line: &str) -> Result<String> {
+	connection.get_mut().write_all(format!("{}\n", line).as_bytes()).await?;
+
+	let mut reply = String::new();
+	connection.read_line(&mut reply).await?;
+
+	Ok(reply.trim_end().to_owned())
+}
+
+#[tokio::test]
+async fn hello_agrees_on_the_highest_common_version() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+
+	let mut connection = BufReader::new(TcpStream::connect(bridge.sam_address()).await?);
+	let reply = SamMessage::parse(&exchange(&mut connection, "HELLO VERSION MIN=3.0 MAX=3.1").await?)?;
+	assert_eq!(reply.get("VERSION"), Some("3.1"));
+
+	let mut connection = BufReader::new(TcpStream::connect(bridge.sam_address()).await?);
+	let reply = SamMessage::parse(&exchange(&mut connection, "HELLO VERSION MIN=3.4 MAX=4.0").await?)?;
+	assert_eq!(reply.get("RESULT"), Some("NOVERSION"));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn transient_sessions_return_their_keys() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+
+	let mut connection = BufReader::new(TcpStream::connect(bridge.sam_address()).await?);
+	exchange(&mut connection, "HELLO VERSION").await?;
+
+	assert_eq!(exchange(&mut connection, "PING 1234").await?, "PONG 1234");
+
+	let reply = SamMessage::parse(&exchange(&mut connection, "SESSION CREATE STYLE=STREAM ID=transient DESTINATION=TRANSIENT").await?)?;
+	assert_eq!(reply.get("RESULT"), Some("OK"));
+
+	let keys = PrivateKeys::from_base64(reply.require("DESTINATION")?)?;
+
+	let reply = SamMessage::parse(&exchange(&mut connection, "NAMING LOOKUP NAME=ME").await?)?;
+	assert_eq!(reply.get("VALUE"), Some(keys.destination().to_base64().as_str()));
+
+	let reply = SamMessage::parse(&exchange(&mut connection, "SESSION CREATE STYLE=STREAM ID=transient DESTINATION=TRANSIENT").await?)?;
+	assert_eq!(reply.get("RESULT"), Some("DUPLICATED_ID"));
+
+	Ok(())
+}
+
+/// Connects, says HELLO and creates a session with `options`, returning the connection and the session's destination.
+async fn create_session(bridge: &Bridge, options: &str) -> Result<(BufReader<TcpStream>, String)> {
+	let mut connection = BufReader::new(TcpStream::connect(bridge.sam_address()).await?);
+	exchange(&mut connection, "HELLO VERSION").await?;
+
+	let reply = SamMessage::parse(&exchange(&mut connection, &format!("SESSION CREATE DESTINATION=TRANSIENT {}", options)).await?)?;
+	assert_eq!(reply.get("RESULT"), Some("OK"));
+
+	let keys = PrivateKeys::from_base64(reply.require("DESTINATION")?)?;
+
+	Ok((connection, keys.destination().to_base64()))
+}
+
+#[tokio::test]
+async fn datagrams_are_sent_and_received_on_the_control_connection() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+
+	let (mut receiver, receiver_destination) = create_session(&bridge, "STYLE=DATAGRAM ID=tcp_receiver").await?;
+	let (mut sender, sender_destination) = create_session(&bridge, "STYLE=DATAGRAM ID=tcp_sender").await?;
+
+	let send = format!("DATAGRAM SEND DESTINATION={} SIZE=5 TO_PORT=7\nhello", receiver_destination);
+	sender.get_mut().write_all(send.as_bytes()).await?;
+
+	let mut line = String::new();
+	receiver.read_line(&mut line).await?;
+
+	let received = SamMessage::parse(&line)?;
+	assert_eq!(
+		(received.topic.as_str(), received.subtopic.as_deref()),
+		("DATAGRAM", Some("RECEIVED"))
+	);
+	assert_eq!(received.get("DESTINATION"), Some(sender_destination.as_str()));
+	assert_eq!(received.get("SIZE"), Some("5"));
+	assert_eq!(received.get("TO_PORT"), Some("7"));
+
+	let mut contents = [0u8; 5];
+	receiver.read_exact(&mut contents).await?;
+	assert_eq!(&contents, b"hello");
+
+	// The connection still takes commands after a datagram.
+	assert_eq!(exchange(&mut sender, "PING after").await?, "PONG after");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn raw_datagrams_are_received_on_the_control_connection() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+
+	let (mut receiver, receiver_destination) = create_session(&bridge, "STYLE=RAW ID=raw_receiver").await?;
+	let (mut sender, _) = create_session(&bridge, "STYLE=RAW ID=raw_sender PROTOCOL=20").await?;
+
+	let send = format!("RAW SEND DESTINATION={} SIZE=3 FROM_PORT=4\nraw", receiver_destination);
+	sender.get_mut().write_all(send.as_bytes()).await?;
+
+	let mut line = String::new();
+	receiver.read_line(&mut line).await?;
+
+	let received = SamMessage::parse(&line)?;
+	assert_eq!((received.topic.as_str(), received.subtopic.as_deref()), ("RAW", Some("RECEIVED")));
+	assert_eq!(received.get("DESTINATION"), None);
+	assert_eq!(received.get("FROM_PORT"), Some("4"));
+	assert_eq!(received.get("PROTOCOL"), Some("20"));
+
+	let mut contents = [0u8; 3];
+	receiver.read_exact(&mut contents).await?;
+	assert_eq!(&contents, b"raw");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn malformed_commands_are_answered() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+
+	let mut connection = BufReader::new(TcpStream::connect(bridge.sam_address()).await?);
+	exchange(&mut connection, "HELLO VERSION").await?;
+
+	let reply = SamMessage::parse(&exchange(&mut connection, "NAMING LOOKUP NAME=\"unterminated").await?)?;
+	assert_eq!(reply.topic, "NAMING");
+	assert_eq!(reply.get("RESULT"), Some("I2P_ERROR"));
+
+	assert_eq!(exchange(&mut connection, "PING still there").await?, "PONG still there");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn bridges_sharing_a_transport_reach_each_other() -> Result<()> {
+	let transport = LoopbackTransport::new();
+
+	let first = Bridge::start(transport.clone()).await?;
+	let second = Bridge::start(transport).await?;
+
+	let server = Session::builder("shared_server", SessionStyle::Stream)
+		.config(first.config())
+		.build()
+		.await?;
+	let client = Session::builder("shared_client", SessionStyle::Stream)
+		.config(second.config())
+		.build()
+		.await?;
+
+	let mut listener = server.listen().await?;
+
+	let accept = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await?;
+
+		let mut buffer = [0u8; 5];
+		stream.read_exact(&mut buffer).await?;
+
+		anyhow::Ok(buffer)
+	});
+
+	let mut stream = client.connect_stream(&server.public_key).await?;
+	stream.write_all(b"hello").await?;
+
+	assert_eq!(&accept.await??, b"hello");
+
+	Ok(())
+}
+
+/// Connects with SAM version `version` and sends STREAM ACCEPT for `service`, returning the connection and the reply.
+async fn accept(bridge: &Bridge, service: &str, version: &str) -> Result<(BufReader<TcpStream>, Option<String>)> {
+	let mut connection = BufReader::new(TcpStream::connect(bridge.sam_address()).await?);
+	exchange(&mut connection, &format!("HELLO VERSION MAX={}", version)).await?;
+
+	let reply = SamMessage::parse(&exchange(&mut connection, &format!("STREAM ACCEPT ID={}", service)).await?)?;
+
+	Ok((connection, reply.get("RESULT").map(str::to_owned)))
+}
+
+/// Reads the header line and the first five bytes of a stream handed to an acceptor, returning TO_PORT and the bytes.
+async fn read_accepted(connection: &mut BufReader<TcpStream>) -> Result<(String, [u8; 5])> {
+	let mut header = String::new();
+	connection.read_line(&mut header).await?;
+
+	let mut buffer = [0u8; 5];
+	connection.read_exact(&mut buffer).await?;
+
+	let to_port = header
+		.trim_end()
+		.rsplit_once("TO_PORT=")
+		.map(|(_, port)| port.to_owned())
+		.unwrap_or_default();
+
+	Ok((to_port, buffer))
+}
+
+#[tokio::test]
+async fn accepts_that_hung_up_are_skipped() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+	let (_control, destination) = create_session(&bridge, "STYLE=STREAM ID=hung_up_server").await?;
+
+	let (gone, result) = accept(&bridge, "hung_up_server", "3.3").await?;
+	assert_eq!(result.as_deref(), Some("OK"));
+	drop(gone);
+
+	let (mut waiting, result) = accept(&bridge, "hung_up_server", "3.3").await?;
+	assert_eq!(result.as_deref(), Some("OK"));
+
+	let client = Session::builder("hung_up_client", SessionStyle::Stream)
+		.config(bridge.config())
+		.build()
+		.await?;
+	let mut stream = client.connect_stream(destination).await?;
+	stream.write_all(b"hello").await?;
+
+	assert_eq!(&read_accepted(&mut waiting).await?.1, b"hello");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn second_accepts_need_sam_3_2() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+	let (_control, _) = create_session(&bridge, "STYLE=STREAM ID=accept_server").await?;
+
+	let (_first, result) = accept(&bridge, "accept_server", "3.1").await?;
+	assert_eq!(result.as_deref(), Some("OK"));
+
+	let (_second, result) = accept(&bridge, "accept_server", "3.1").await?;
+	assert_eq!(result.as_deref(), Some("ALREADY_ACCEPTING"));
+
+	let (_third, result) = accept(&bridge, "accept_server", "3.2").await?;
+	assert_eq!(result.as_deref(), Some("OK"));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn streams_prefer_sessions_on_their_port() -> Result<()> {
+	let bridge = Bridge::start(LoopbackTransport::new()).await?;
+	let (mut control, destination) = create_session(&bridge, "STYLE=PRIMARY ID=ports_primary").await?;
+
+	for options in ["STYLE=STREAM ID=ports_any", "STYLE=STREAM ID=ports_web LISTEN_PORT=80"] {
+		let reply = SamMessage::parse(&exchange(&mut control, &format!("SESSION ADD {}", options)).await?)?;
+		assert_eq!(reply.get("RESULT"), Some("OK"));
+	}
+
+	let (mut any, _) = accept(&bridge, "ports_any", "3.3").await?;
+	let (mut web, _) = accept(&bridge, "ports_web", "3.3").await?;
+
+	let client = Session::builder("ports_client", SessionStyle::Stream)
+		.config(bridge.config())
+		.build()
+		.await?;
+
+	let mut stream = client.connect_stream_with_ports(destination.clone(), 0, 80).await?;
+	stream.write_all(b"web!!").await?;
+	assert_eq!(read_accepted(&mut web).await?, ("80".to_owned(), *b"web!!"));
+
+	let mut stream = client.connect_stream_with_ports(destination, 0, 22).await?;
+	stream.write_all(b"other").await?;
+	assert_eq!(read_accepted(&mut any).await?, ("22".to_owned(), *b"other"));
+
+	Ok(())
+}
@@ -0,0 +1,81 @@
+use solitude::testing::MockBridge;
+use solitude::{
+	Error, Operation, PrivateKeys, SamConfig, Session, SessionStyle, SignatureType, Timeouts, DATAGRAM_ADDRESS_VARIABLE,
+	DEFAULT_DATAGRAM_ADDRESS, DEFAULT_SAM_ADDRESS, SAM_ADDRESS_VARIABLE,
+};
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+#[test]
+fn config_reads_sam_address_from_variables() {
+	let config = SamConfig::from_variables(|name| (name == SAM_ADDRESS_VARIABLE).then(|| "sam.example:7777".to_owned()));
+
+	assert_eq!(config.sam_address, "sam.example:7777");
+	assert_eq!(config.datagram_address, DEFAULT_DATAGRAM_ADDRESS);
+}
+
+#[test]
+fn config_reads_datagram_address_from_variables() {
+	let config = SamConfig::from_variables(|name| (name == DATAGRAM_ADDRESS_VARIABLE).then(|| "sam.example:7778".to_owned()));
+
+	assert_eq!(config.sam_address, DEFAULT_SAM_ADDRESS);
+	assert_eq!(config.datagram_address, "sam.example:7778");
+}
+
+#[tokio::test]
+async fn builder_connects_to_configured_address() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	Session::builder("builder_connects_to_configured_address", SessionStyle::Stream)
+		.sam_address(bridge.config().sam_address)
+		.connect_timeout(Duration::from_secs(5))
+		.build()
+		.await?;
+
+	let commands = bridge.commands();
+	assert_eq!(commands.first().map(|command| command.topic.as_str()), Some("HELLO"));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn unanswered_hello_times_out() -> Result<()> {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+	let address = listener.local_addr()?.to_string();
+
+	tokio::task::spawn(async move {
+		let (_silent_bridge, _) = listener.accept().await.unwrap();
+		tokio::time::sleep(Duration::from_secs(60)).await;
+	});
+
+	let timeouts = Timeouts {
+		hello: Duration::from_millis(200),
+		..Timeouts::default()
+	};
+
+	let result = Session::builder("unanswered_hello_times_out", SessionStyle::Stream)
+		.sam_address(address)
+		.timeouts(timeouts)
+		.build()
+		.await;
+
+	assert!(matches!(result, Err(Error::Timeout(Operation::Hello))));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn keys_of_another_signature_type_are_rejected() -> Result<()> {
+	let result = Session::builder("keys_of_another_signature_type_are_rejected", SessionStyle::Stream)
+		.sam_address("127.0.0.1:1")
+		.keys(PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?)
+		.signature_type(SignatureType::DsaSha1)
+		.build()
+		.await;
+
+	assert!(matches!(result, Err(Error::Address(_))));
+
+	Ok(())
+}
@@ -1,7 +1,8 @@
 #[macro_use]
 extern crate log;
 
-use solitude::{DatagramMessage, Session, SessionStyle};
+use solitude::testing::MockBridge;
+use solitude::{DatagramMessage, I2pDatagramSocket, RawDatagramMessage, RawDatagramSocket, SessionStyle};
 
 use std::time::Duration;
 
@@ -9,26 +10,26 @@ use anyhow::{Context, Result};
 
 use env_logger::Target;
 
-async fn init() {
+/// Starts a mock bridge, so the tests run without an I2P router.
+async fn init() -> Result<MockBridge> {
 	let _ = env_logger::builder()
 		.is_test(true)
 		.format_module_path(true)
 		.target(Target::Stdout)
 		.try_init();
 
-	tokio::time::sleep(Duration::from_secs(10)).await;
+	Ok(MockBridge::start().await?)
 }
 
 #[tokio::test]
 async fn can_create_datagram_session() -> Result<()> {
-	eprintln!("init");
-	init().await;
+	let bridge = init().await?;
 
-	eprintln!("inited");
-	let mut session = Session::new("can_create_datagram_session", SessionStyle::Datagram).await?;
-	eprintln!("Create");
+	let mut session = bridge
+		.session_builder("can_create_datagram_session", SessionStyle::Datagram)
+		.build()
+		.await?;
 	session.forward("127.0.0.1", 0).await?;
-	eprintln!("forward");
 
 	session.close().await?;
 	Ok(())
@@ -36,9 +37,9 @@ async fn can_create_datagram_session() -> Result<()> {
 
 #[tokio::test]
 async fn can_create_raw_session() -> Result<()> {
-	init().await;
+	let bridge = init().await?;
 
-	let mut session = Session::new("can_create_raw_session", SessionStyle::Raw).await?;
+	let mut session = bridge.session_builder("can_create_raw_session", SessionStyle::Raw).build().await?;
 	session.forward("127.0.0.1", 0).await?;
 
 	Ok(())
@@ -57,32 +58,34 @@ async fn can_send_datagram_to_service() -> Result<()> {
 }
 
 async fn can_send_datagram_or_raw_to_service(name: &str, session_style: SessionStyle) -> Result<()> {
-	init().await;
+	let bridge = init().await?;
+	let datagram_address = bridge.config().datagram_address;
 
 	let server_socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
-	server_socket.connect("127.0.0.1:7655").await?;
+	server_socket.connect(&datagram_address).await?;
 
 	let server_port = server_socket.local_addr()?.port();
 
-	let mut server_session = Session::new(format!("{}_server", name), session_style).await?;
+	let mut server_session = bridge.session_builder(format!("{}_server", name), session_style).build().await?;
 	server_session.forward("127.0.0.1", server_port).await?;
 
 	info!("server on 127.0.0.1:{} or {}", server_port, server_session.address()?);
 
-	//I2Pd can take a while to shuffle...
-	tokio::time::sleep(Duration::from_secs(3)).await;
-
 	let client_socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
-	client_socket.connect("127.0.0.1:7655").await?;
+	client_socket.connect(&datagram_address).await?;
 
 	let client_port = client_socket.local_addr()?.port();
 
-	let mut client_session = Session::new(format!("{}_client", name), session_style).await?;
+	let mut client_session = bridge.session_builder(format!("{}_client", name), session_style).build().await?;
 	client_session.forward("127.0.0.1", client_port).await?;
 
 	info!("client on 127.0.0.1:{} or {}", client_port, client_session.address()?);
 
-	let datagram = DatagramMessage::new(format!("{}_client", name), server_session.public_key, b"Hello World!".to_vec());
+	let datagram = DatagramMessage::new(
+		format!("{}_client", name),
+		server_session.public_key.to_base64(),
+		b"Hello World!".to_vec(),
+	);
 	let datagram_bytes = datagram.serialize();
 
 	let handle = tokio::task::spawn(async move {
@@ -104,9 +107,112 @@ async fn can_send_datagram_or_raw_to_service(name: &str, session_style: SessionS
 }
 
 #[tokio::test]
-async fn can_create_datagram_message() -> Result<()> {
-	init().await;
+async fn datagram_sockets_can_exchange_datagrams() -> Result<()> {
+	let bridge = init().await?;
+
+	let server_socket = I2pDatagramSocket::from_session(
+		bridge
+			.session_builder("datagram_sockets_can_exchange_datagrams_server", SessionStyle::Datagram)
+			.build()
+			.await?,
+	)
+	.await?;
+	let client_socket = I2pDatagramSocket::from_session(
+		bridge
+			.session_builder("datagram_sockets_can_exchange_datagrams_client", SessionStyle::Datagram)
+			.build()
+			.await?,
+	)
+	.await?;
+
+	let server_destination = server_socket.local_destination().to_owned();
+
+	let handle = tokio::task::spawn(async move { server_socket.recv_from().await });
+
+	for _ in 0..10 {
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		client_socket.send_to(b"Hello World!", &server_destination).await?;
+	}
+
+	let (contents, destination) = handle.await??;
+
+	assert_eq!(contents, b"Hello World!");
+	assert_eq!(&destination, client_socket.local_destination());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn raw_datagram_sockets_can_exchange_datagrams() -> Result<()> {
+	let bridge = init().await?;
+
+	let server_socket = RawDatagramSocket::from_session(
+		bridge
+			.session_builder("raw_datagram_sockets_can_exchange_datagrams_server", SessionStyle::Raw)
+			.build()
+			.await?,
+		true,
+	)
+	.await?;
+	let client_socket = RawDatagramSocket::from_session(
+		bridge
+			.session_builder("raw_datagram_sockets_can_exchange_datagrams_client", SessionStyle::Raw)
+			.build()
+			.await?,
+		false,
+	)
+	.await?;
+
+	let server_destination = server_socket.local_destination().to_owned();
+
+	let handle = tokio::task::spawn(async move { server_socket.recv().await });
+
+	for _ in 0..10 {
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		client_socket.send_to(b"Hello World!", &server_destination).await?;
+	}
+
+	let datagram = handle.await??;
+
+	assert_eq!(datagram.contents, b"Hello World!");
+	assert!(datagram.to_port.is_some());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn can_deserialize_raw_datagram_message() -> Result<()> {
+	let without_header = RawDatagramMessage::from_bytes(b"Hello\nWorld!", false)?;
+	assert_eq!(without_header, RawDatagramMessage::new(b"Hello\nWorld!".to_vec()));
+
+	let with_header = RawDatagramMessage::from_bytes(b"FROM_PORT=1234 TO_PORT=0 PROTOCOL=18\nHello World!", true)?;
+	assert_eq!(with_header.from_port, Some(1234));
+	assert_eq!(with_header.to_port, Some(0));
+	assert_eq!(with_header.protocol, Some(18));
+	assert_eq!(with_header.contents, b"Hello World!");
 
+	assert!(RawDatagramMessage::from_bytes(b"FROM_PORT=123456\nHello", true).is_err());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn raw_datagram_message_round_trips() -> Result<()> {
+	let contents: [u8; 32] = rand::random();
+
+	let mut datagram = RawDatagramMessage::new(contents.to_vec());
+	assert_eq!(datagram.serialize(), contents.to_vec());
+	assert_eq!(RawDatagramMessage::from_bytes(&datagram.serialize(), false)?, datagram);
+
+	datagram.from_port = Some(7);
+	datagram.to_port = Some(8);
+	assert_eq!(RawDatagramMessage::from_bytes(&datagram.serialize(), true)?, datagram);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn can_create_datagram_message() -> Result<()> {
 	let contents: [u8; 32] = rand::random();
 	let _datagram_message = DatagramMessage::new("test", "test_destination", contents.to_vec());
 
@@ -115,8 +221,6 @@ async fn can_create_datagram_message() -> Result<()> {
 
 #[tokio::test]
 async fn can_serialize_datagram_message() -> Result<()> {
-	init().await;
-
 	let contents: [u8; 32] = rand::random();
 	let datagram_message = DatagramMessage::new("test", "test_destination", contents.to_vec());
 	let _datagram_message_bytes = datagram_message.serialize();
@@ -126,8 +230,6 @@ async fn can_serialize_datagram_message() -> Result<()> {
 
 #[tokio::test]
 async fn can_deserialize_datagram_message() -> Result<()> {
-	init().await;
-
 	let example_received_datagram_bytes = [
 		0x4a, 0x37, 0x61, 0x67, 0x75, 0x4b, 0x7e, 0x6a, 0x6c, 0x65, 0x75, 0x7e, 0x7a, 0x50, 0x7a, 0x64, 0x63, 0x64, 0x59, 0x36, 0x77, 0x47,
 		0x47, 0x6c, 0x6d, 0x6c, 0x64, 0x6d, 0x53, 0x57, 0x47, 0x57, 0x30, 0x78, 0x4b, 0x7e, 0x65, 0x34, 0x62, 0x6f, 0x42, 0x31, 0x43, 0x7a,
@@ -165,3 +267,34 @@ async fn can_deserialize_datagram_message() -> Result<()> {
 
 	Ok(())
 }
+
+#[tokio::test]
+async fn can_serialize_datagram_message_with_options() -> Result<()> {
+	let mut datagram_message = DatagramMessage::new("test", "test_destination", b"Hello World!".to_vec());
+	assert!(datagram_message.serialize().starts_with(b"3.0 test test_destination\n"));
+
+	datagram_message.from_port = Some(1234);
+	datagram_message.to_port = Some(80);
+	datagram_message.send_leaseset = Some(false);
+
+	assert_eq!(
+		datagram_message.serialize(),
+		b"3.2 test test_destination FROM_PORT=1234 TO_PORT=80 SEND_LEASESET=false\nHello World!".to_vec()
+	);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn can_deserialize_datagram_message_with_ports() -> Result<()> {
+	let datagram_message = DatagramMessage::from_bytes("test", b"test_destination FROM_PORT=1234 TO_PORT=80\nHello World!")?;
+
+	assert_eq!(datagram_message.destination, "test_destination");
+	assert_eq!(datagram_message.from_port, Some(1234));
+	assert_eq!(datagram_message.to_port, Some(80));
+	assert_eq!(datagram_message.contents, b"Hello World!");
+
+	assert!(DatagramMessage::from_bytes("test", b"test_destination TO_PORT=http\nHello World!").is_err());
+
+	Ok(())
+}
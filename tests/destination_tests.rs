@@ -0,0 +1,210 @@
+use solitude::{Destination, EncryptionType, Error, PrivateKeys, SignatureType};
+
+use anyhow::Result;
+use rand::Rng;
+
+/// Builds a destination with a key certificate, placing the keys the way the router does.
+fn key_certificate_destination(signature_type: SignatureType, public_key: &[u8], signing_key: &[u8]) -> Vec<u8> {
+	let mut bytes = public_key.to_vec();
+	bytes.resize(256, 0xaa);
+
+	let padding = 128usize.saturating_sub(signing_key.len());
+	bytes.extend(std::iter::repeat_n(0xbb, padding));
+	bytes.extend_from_slice(&signing_key[..signing_key.len().min(128)]);
+
+	let excess = signing_key.get(128..).unwrap_or_default();
+
+	bytes.push(5);
+	bytes.extend_from_slice(&(4 + excess.len() as u16).to_be_bytes());
+	bytes.extend_from_slice(&signature_type.code().to_be_bytes());
+	bytes.extend_from_slice(&EncryptionType::X25519.code().to_be_bytes());
+	bytes.extend_from_slice(excess);
+
+	bytes
+}
+
+fn random_bytes(length: usize) -> Vec<u8> {
+	let mut rng = rand::thread_rng();
+
+	(0..length).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn can_decode_null_certificate_destination() -> Result<()> {
+	let mut bytes = random_bytes(384);
+	bytes.extend_from_slice(&[0, 0, 0]);
+
+	let destination = Destination::from_bytes(&bytes)?;
+
+	assert_eq!(destination.signature_type(), SignatureType::DsaSha1);
+	assert_eq!(destination.encryption_type(), EncryptionType::ElGamal);
+	assert_eq!(destination.public_key(), &bytes[..256]);
+	assert_eq!(destination.signing_public_key(), &bytes[256..384]);
+	assert_eq!(destination.certificate().certificate_type, 0);
+	assert_eq!(destination.to_base64().len(), 516);
+
+	Ok(())
+}
+
+#[test]
+fn can_decode_key_certificate_destination() -> Result<()> {
+	let public_key = random_bytes(32);
+	let signing_key = random_bytes(32);
+	let bytes = key_certificate_destination(SignatureType::EdDsaSha512Ed25519, &public_key, &signing_key);
+
+	let destination: Destination = Destination::from_bytes(&bytes)?.to_base64().parse()?;
+
+	assert_eq!(destination.signature_type(), SignatureType::EdDsaSha512Ed25519);
+	assert_eq!(destination.encryption_type(), EncryptionType::X25519);
+	assert_eq!(destination.public_key(), &public_key[..]);
+	assert_eq!(destination.signing_public_key(), signing_key);
+	assert_eq!(destination.certificate().payload, vec![0, 7, 0, 4]);
+	assert_eq!(destination.to_bytes(), bytes);
+
+	Ok(())
+}
+
+#[test]
+fn can_decode_signing_key_spilling_into_certificate() -> Result<()> {
+	let signing_key = random_bytes(512);
+	let bytes = key_certificate_destination(SignatureType::RsaSha512_4096, &random_bytes(32), &signing_key);
+
+	let destination = Destination::from_bytes(&bytes)?;
+
+	assert_eq!(destination.signing_public_key(), signing_key);
+
+	Ok(())
+}
+
+#[test]
+fn base64_round_trips_and_tolerates_missing_padding() -> Result<()> {
+	let bytes = key_certificate_destination(SignatureType::EdDsaSha512Ed25519, &random_bytes(32), &random_bytes(32));
+	let destination = Destination::from_bytes(&bytes)?;
+
+	let base64 = destination.to_base64();
+	assert!(base64.ends_with("=="));
+
+	assert_eq!(Destination::from_base64(&base64)?, destination);
+	assert_eq!(Destination::from_base64(base64.trim_end_matches('='))?, destination);
+	assert_eq!(destination.to_string(), base64);
+
+	Ok(())
+}
+
+#[test]
+fn address_is_hash_of_destination() -> Result<()> {
+	let bytes = key_certificate_destination(SignatureType::EdDsaSha512Ed25519, &random_bytes(32), &random_bytes(32));
+	let destination = Destination::from_bytes(&bytes)?;
+
+	let address = destination.b32_address().to_string();
+
+	assert_eq!(address.len(), 52 + ".b32.i2p".len());
+	assert!(address.ends_with(".b32.i2p"));
+	assert_eq!(address, Destination::from_bytes(&bytes)?.b32_address().to_string());
+
+	Ok(())
+}
+
+#[test]
+fn rejects_malformed_destinations() {
+	let valid = key_certificate_destination(SignatureType::EdDsaSha512Ed25519, &random_bytes(32), &random_bytes(32));
+
+	let mut unknown_signature = valid.clone();
+	unknown_signature[388] = 99;
+
+	let mut unknown_certificate = valid.clone();
+	unknown_certificate[384] = 3;
+
+	let mut trailing = valid.clone();
+	trailing.push(0);
+
+	for bytes in [
+		valid[..300].to_vec(),
+		valid[..390].to_vec(),
+		unknown_signature,
+		unknown_certificate,
+		trailing,
+	] {
+		assert!(matches!(Destination::from_bytes(&bytes), Err(Error::Address(_))));
+	}
+
+	assert!(Destination::from_base64("not base64!").is_err());
+}
+
+#[test]
+fn can_split_private_keys() -> Result<()> {
+	let destination_bytes = key_certificate_destination(SignatureType::EdDsaSha512Ed25519, &random_bytes(32), &random_bytes(32));
+	let private_key = random_bytes(32);
+	let signing_private_key = random_bytes(32);
+
+	let mut bytes = destination_bytes.clone();
+	bytes.extend_from_slice(&private_key);
+	bytes.extend_from_slice(&signing_private_key);
+
+	let private_keys = PrivateKeys::from_bytes(&bytes)?;
+
+	assert_eq!(private_keys.destination().to_bytes(), destination_bytes);
+	assert_eq!(private_keys.private_key(), &private_key[..]);
+	assert_eq!(private_keys.signing_private_key(), &signing_private_key[..]);
+	assert_eq!(private_keys.offline_signature(), None);
+	assert_eq!(PrivateKeys::from_base64(&private_keys.to_base64())?, private_keys);
+
+	let rebuilt = PrivateKeys::from_parts(private_keys.destination().clone(), private_key, signing_private_key)?;
+	assert_eq!(rebuilt, private_keys);
+
+	assert!(PrivateKeys::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+	assert!(!format!("{:?}", private_keys).contains(&format!("{:?}", private_keys.private_key())));
+
+	Ok(())
+}
+
+#[test]
+fn can_generate_ed25519_keys() -> Result<()> {
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let destination = private_keys.destination();
+
+	assert_eq!(destination.signature_type(), SignatureType::EdDsaSha512Ed25519);
+	assert_eq!(destination.encryption_type(), EncryptionType::X25519);
+	assert_eq!(destination.certificate().payload, vec![0, 7, 0, 4]);
+
+	let signing_key = ed25519_dalek::SigningKey::from_bytes(private_keys.signing_private_key().try_into()?);
+	assert_eq!(destination.signing_public_key(), signing_key.verifying_key().as_bytes());
+
+	assert_eq!(PrivateKeys::from_base64(&private_keys.to_base64())?, private_keys);
+	assert_ne!(PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination(), destination);
+
+	Ok(())
+}
+
+#[test]
+fn can_generate_x25519_keys() -> Result<()> {
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	let secret: [u8; 32] = private_keys.private_key().try_into()?;
+	let public_key = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(secret));
+
+	assert_eq!(private_keys.destination().encryption_type(), EncryptionType::X25519);
+	assert_eq!(private_keys.destination().public_key(), public_key.as_bytes());
+
+	let elgamal = PrivateKeys::generate_with_encryption(SignatureType::EdDsaSha512Ed25519, EncryptionType::ElGamal)?;
+	assert_eq!(elgamal.destination().encryption_type(), EncryptionType::ElGamal);
+
+	Ok(())
+}
+
+#[test]
+fn rejects_generating_unsupported_keys() {
+	assert!(PrivateKeys::generate(SignatureType::DsaSha1).is_err());
+	assert!(PrivateKeys::generate_with_encryption(SignatureType::EdDsaSha512Ed25519, EncryptionType::EcP256).is_err());
+}
+
+#[test]
+fn signature_types_parse_from_sam_names_and_codes() -> Result<()> {
+	assert_eq!("EdDSA_SHA512_Ed25519".parse::<SignatureType>()?, SignatureType::EdDsaSha512Ed25519);
+	assert_eq!("ecdsa_sha256_p256".parse::<SignatureType>()?, SignatureType::EcdsaSha256P256);
+	assert_eq!("11".parse::<SignatureType>()?, SignatureType::RedDsaSha512Ed25519);
+	assert_eq!(SignatureType::DsaSha1.to_string(), "DSA_SHA1");
+	assert!("ED25519".parse::<SignatureType>().is_err());
+
+	Ok(())
+}
@@ -0,0 +1,43 @@
+use solitude::{Error, SamResult};
+
+#[test]
+fn sam_result_codes_round_trip() {
+	for code in [
+		"CANT_REACH_PEER",
+		"DUPLICATED_ID",
+		"INVALID_KEY",
+		"KEY_NOT_FOUND",
+		"TIMEOUT",
+		"SOMETHING_NEW",
+	] {
+		assert_eq!(SamResult::from_code(code).as_code(), code);
+	}
+
+	assert_eq!(SamResult::from_code("DUPLICATED_ID"), SamResult::DuplicatedId);
+	assert_eq!(SamResult::from_code("SOMETHING_NEW"), SamResult::Other("SOMETHING_NEW".to_owned()));
+}
+
+#[test]
+fn sam_errors_expose_result_and_message() {
+	let error = Error::Sam {
+		result: SamResult::CantReachPeer,
+		message: Some("no leaseset".to_owned()),
+	};
+
+	assert_eq!(error.sam_result(), Some(&SamResult::CantReachPeer));
+	assert_eq!(error.to_string(), "SAM bridge returned CANT_REACH_PEER: no leaseset");
+	assert!(!error.is_timeout());
+}
+
+#[test]
+fn lookup_errors_name_what_was_looked_up() {
+	let error = Error::Lookup {
+		name: "nowhere.i2p".to_owned(),
+		result: SamResult::KeyNotFound,
+		message: None,
+	};
+
+	assert_eq!(error.sam_result(), Some(&SamResult::KeyNotFound));
+	assert_eq!(error.to_string(), "could not look up nowhere.i2p: KEY_NOT_FOUND");
+	assert!(error.is_not_found());
+}
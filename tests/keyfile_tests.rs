@@ -0,0 +1,83 @@
+use solitude::{Error, PrivateKeys, SignatureType};
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+fn keyfile_path(name: &str) -> PathBuf {
+	std::env::temp_dir().join(format!("solitude_{}_{}.dat", name, std::process::id()))
+}
+
+#[test]
+fn plain_keyfile_round_trips() -> Result<()> {
+	let path = keyfile_path("plain_keyfile_round_trips");
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	private_keys.save(&path)?;
+
+	assert_eq!(std::fs::read(&path)?, private_keys.to_bytes());
+	assert_eq!(PrivateKeys::load(&path)?, private_keys);
+
+	std::fs::remove_file(&path)?;
+
+	Ok(())
+}
+
+#[test]
+fn encrypted_keyfile_round_trips() -> Result<()> {
+	let path = keyfile_path("encrypted_keyfile_round_trips");
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	private_keys.save_encrypted(&path, "correct horse battery staple")?;
+
+	let loaded = PrivateKeys::load_encrypted(&path, "correct horse battery staple");
+	let wrong_passphrase = PrivateKeys::load_encrypted(&path, "wrong");
+	let without_passphrase = PrivateKeys::load(&path);
+
+	std::fs::remove_file(&path)?;
+
+	assert_eq!(loaded?, private_keys);
+	assert!(matches!(wrong_passphrase, Err(Error::Keyfile(_))));
+	assert!(matches!(without_passphrase, Err(Error::Keyfile(_))));
+
+	Ok(())
+}
+
+#[test]
+fn tampered_encrypted_keys_are_rejected() -> Result<()> {
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let bytes = private_keys.to_encrypted_bytes("passphrase")?;
+
+	let mut tampered_salt = bytes.clone();
+	tampered_salt[30] ^= 1;
+
+	let mut tampered_keys = bytes.clone();
+	*tampered_keys.last_mut().unwrap() ^= 1;
+
+	for bytes in [tampered_salt, tampered_keys, bytes[..40].to_vec(), private_keys.to_bytes()] {
+		assert!(matches!(
+			PrivateKeys::from_encrypted_bytes(&bytes, "passphrase"),
+			Err(Error::Keyfile(_))
+		));
+	}
+
+	Ok(())
+}
+
+#[test]
+fn excessive_argon2_costs_are_rejected() -> Result<()> {
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let bytes = private_keys.to_encrypted_bytes("passphrase")?;
+
+	for offset in [9, 13, 17] {
+		let mut costly = bytes.clone();
+		costly[offset..offset + 4].copy_from_slice(&0x0FFF_FFFFu32.to_be_bytes());
+
+		assert!(matches!(
+			PrivateKeys::from_encrypted_bytes(&costly, "passphrase"),
+			Err(Error::Keyfile(_))
+		));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,59 @@
+use solitude::{ClientKey, EncryptedLeaseSet, LeaseSetAuth};
+
+fn option<'a>(options: &'a [(String, String)], key: &str) -> Option<&'a str> {
+	options.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn encrypted_lease_set_without_auth() {
+	let options = EncryptedLeaseSet::default().options();
+
+	assert_eq!(options, vec![("i2cp.leaseSetType".to_owned(), "5".to_owned())]);
+}
+
+#[test]
+fn encrypted_lease_set_with_secret_and_psk_clients() {
+	let encrypted_lease_set = EncryptedLeaseSet {
+		secret: Some("hunter2".to_owned()),
+		auth: LeaseSetAuth::Psk(vec![ClientKey::new("alice", [0; 32]), ClientKey::new("bob", [0xff; 32])]),
+	};
+
+	let options = encrypted_lease_set.options();
+
+	assert_eq!(option(&options, "i2cp.leaseSetType"), Some("5"));
+	assert_eq!(option(&options, "i2cp.leaseSetSecret"), Some("aHVudGVyMg=="));
+	assert_eq!(option(&options, "i2cp.leaseSetAuthType"), Some("2"));
+	assert_eq!(
+		option(&options, "i2cp.leaseSetClient.psk.0"),
+		Some("YWxpY2U=:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+	);
+	assert!(option(&options, "i2cp.leaseSetClient.psk.1").is_some_and(|value| value.starts_with("Ym9i:~~~~")));
+}
+
+#[test]
+fn encrypted_lease_set_with_dh_clients() {
+	let encrypted_lease_set = EncryptedLeaseSet {
+		secret: None,
+		auth: LeaseSetAuth::Dh(vec![ClientKey::new("carol", [1; 32])]),
+	};
+
+	let options = encrypted_lease_set.options();
+
+	assert_eq!(option(&options, "i2cp.leaseSetAuthType"), Some("1"));
+	assert!(option(&options, "i2cp.leaseSetClient.dh.0").is_some());
+	assert_eq!(option(&options, "i2cp.leaseSetSecret"), None);
+}
+
+#[test]
+fn client_names_cannot_break_options() {
+	let encrypted_lease_set = EncryptedLeaseSet {
+		secret: None,
+		auth: LeaseSetAuth::Psk(vec![ClientKey::new("mallory: the tester", [0; 32])]),
+	};
+
+	let options = encrypted_lease_set.options();
+	let value = option(&options, "i2cp.leaseSetClient.psk.0").unwrap();
+
+	assert_eq!(value.matches(':').count(), 1);
+	assert!(!value.contains(' '));
+}
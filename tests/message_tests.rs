@@ -0,0 +1,117 @@
+use solitude::SamMessage;
+
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+#[test]
+fn can_parse_reply() -> Result<()> {
+	let message = SamMessage::parse("HELLO REPLY RESULT=OK VERSION=3.1\n")?;
+
+	assert!(message.is("HELLO", "REPLY"));
+	assert_eq!(message.get("RESULT"), Some("OK"));
+	assert_eq!(message.get("VERSION"), Some("3.1"));
+	assert_eq!(message.get("MESSAGE"), None);
+
+	Ok(())
+}
+
+#[test]
+fn can_parse_reply_in_any_order() -> Result<()> {
+	let message = SamMessage::parse("DEST REPLY PRIV=private~key== PUB=public-key")?;
+
+	assert_eq!(message.require("PUB")?, "public-key");
+	assert_eq!(message.require("PRIV")?, "private~key==");
+	assert!(message.require("RESULT").is_err());
+
+	Ok(())
+}
+
+#[test]
+fn can_parse_quoted_values() -> Result<()> {
+	let message = SamMessage::parse(r#"SESSION STATUS RESULT=I2P_ERROR MESSAGE="Duplicate \"dest\" in \\ session""#)?;
+
+	assert_eq!(message.get("RESULT"), Some("I2P_ERROR"));
+	assert_eq!(message.get("MESSAGE"), Some(r#"Duplicate "dest" in \ session"#));
+
+	Ok(())
+}
+
+#[test]
+fn can_parse_keys_without_values() -> Result<()> {
+	let message = SamMessage::parse("NAMING LOOKUP NAME=ME  SILENT \r\n")?;
+
+	assert_eq!(
+		message.pairs,
+		vec![("NAME".to_owned(), "ME".to_owned()), ("SILENT".to_owned(), String::new())]
+	);
+
+	Ok(())
+}
+
+#[test]
+fn rejects_malformed_messages() {
+	for line in [
+		"",
+		"   ",
+		"KEY=VALUE",
+		"A B =C",
+		r#"A B C="unterminated"#,
+		r#"A B C="x"y"#,
+		r#"A B C"D=E"#,
+	] {
+		assert!(SamMessage::parse(line).is_err(), "{:?} should not parse", line);
+	}
+}
+
+#[test]
+fn can_serialize_command() {
+	let message = SamMessage::new("SESSION", "CREATE")
+		.with("STYLE", "STREAM")
+		.with("ID", "my service")
+		.with("PORT", 7655)
+		.with("MESSAGE", "");
+
+	assert_eq!(
+		message.serialize(),
+		"SESSION CREATE STYLE=STREAM ID=\"my service\" PORT=7655 MESSAGE=\"\"\n"
+	);
+}
+
+#[test]
+fn serialization_round_trips_random_messages() -> Result<()> {
+	let mut rng = rand::thread_rng();
+	let key_alphabet: Vec<char> = "ABCXYZ_.0123456789abcz~-".chars().collect();
+	let value_alphabet: Vec<char> = "aZ09 \t\"\\=~-.é".chars().collect();
+
+	for _ in 0..1000 {
+		let mut message = SamMessage::new("TOPIC", "SUBTOPIC");
+
+		for _ in 0..rng.gen_range(0..6) {
+			let key: String = (0..rng.gen_range(1..8)).map(|_| *key_alphabet.choose(&mut rng).unwrap()).collect();
+			let value: String = (0..rng.gen_range(0..12))
+				.map(|_| *value_alphabet.choose(&mut rng).unwrap())
+				.collect();
+
+			message.push(key, value);
+		}
+
+		assert_eq!(SamMessage::parse(&message.serialize())?, message);
+	}
+
+	Ok(())
+}
+
+#[test]
+fn parsing_random_lines_never_panics() {
+	let mut rng = rand::thread_rng();
+	let alphabet: Vec<char> = "AB= \t\"\\\r\nx".chars().collect();
+
+	for _ in 0..10000 {
+		let line: String = (0..rng.gen_range(0..24)).map(|_| *alphabet.choose(&mut rng).unwrap()).collect();
+
+		if let Ok(message) = SamMessage::parse(&line) {
+			assert!(!message.topic.is_empty() || message.subtopic.is_some() || !message.pairs.is_empty());
+		}
+	}
+}
@@ -0,0 +1,346 @@
+use solitude::testing::MockBridge;
+use solitude::{
+	DatagramMessage, Error, I2pDatagramSocket, Operation, PrimarySession, PrivateKeys, RawDatagramSocket, SamResult, SessionStyle,
+	SignatureType, StreamInfo, Timeouts,
+};
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn streams_are_routed_between_mock_destinations() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let server = bridge.session_builder("streams_server", SessionStyle::Stream).build().await?;
+	let client = bridge.session_builder("streams_client", SessionStyle::Stream).build().await?;
+
+	let mut listener = server.listen().await?;
+
+	let accept = tokio::spawn(async move {
+		let (mut stream, peer) = listener.accept().await?;
+
+		let mut buffer = [0u8; 5];
+		stream.read_exact(&mut buffer).await?;
+		stream.write_all(b"world").await?;
+
+		anyhow::Ok((buffer, peer))
+	});
+
+	let mut stream = client.connect_stream(&server.public_key).await?;
+	stream.write_all(b"hello").await?;
+
+	let mut buffer = [0u8; 5];
+	stream.read_exact(&mut buffer).await?;
+
+	let (received, peer) = accept.await??;
+
+	assert_eq!(&received, b"hello");
+	assert_eq!(&buffer, b"world");
+	assert_eq!(peer, client.public_key);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn streams_carry_their_ports() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let server = bridge.session_builder("ports_server", SessionStyle::Stream).build().await?;
+	let client = bridge.session_builder("ports_client", SessionStyle::Stream).build().await?;
+
+	let mut listener = server.listen().await?;
+
+	let stream = client.connect_stream_with_ports(&server.public_key, 1234, 80).await?;
+	assert_eq!((stream.local_port(), stream.peer_port()), (1234, 80));
+
+	let (accepted, _) = listener.accept().await?;
+	assert_eq!((accepted.local_port(), accepted.peer_port()), (80, 1234));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn forwarded_streams_reach_the_tcp_server() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let tcp_listener = TcpListener::bind("127.0.0.1:0").await?;
+
+	let mut server = bridge.session_builder("forwarded_server", SessionStyle::Stream).build().await?;
+	server.forward("127.0.0.1", tcp_listener.local_addr()?.port()).await?;
+
+	let client = bridge.session_builder("forwarded_client", SessionStyle::Stream).build().await?;
+	let mut stream = client.connect_stream(&server.public_key).await?;
+	stream.write_all(b"hello\n").await?;
+
+	let (connection, _) = tcp_listener.accept().await?;
+	let mut reader = BufReader::new(connection);
+
+	let stream_info = StreamInfo::from_bufread(&mut reader).await?;
+	assert_eq!(stream_info.destination, client.public_key.to_base64());
+
+	let mut line = String::new();
+	reader.read_line(&mut line).await?;
+	assert_eq!(line, "hello\n");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn connecting_to_an_unknown_destination_fails() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let client = bridge.session_builder("unknown_client", SessionStyle::Stream).build().await?;
+	let nobody = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	let error = client.connect_stream(nobody.destination()).await.unwrap_err();
+	assert_eq!(error.sam_result(), Some(&SamResult::CantReachPeer));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn datagrams_are_routed_between_mock_destinations() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let alice = I2pDatagramSocket::from_session(bridge.session_builder("datagram_alice", SessionStyle::Datagram).build().await?).await?;
+	let bob = I2pDatagramSocket::from_session(bridge.session_builder("datagram_bob", SessionStyle::Datagram).build().await?).await?;
+
+	alice.send_to(b"hello", bob.local_destination()).await?;
+
+	let (contents, sender) = bob.recv_from().await?;
+	assert_eq!(contents, b"hello");
+	assert_eq!(&sender, alice.local_destination());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn raw_datagrams_carry_ports_and_protocol() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let alice = RawDatagramSocket::from_session(bridge.session_builder("raw_alice", SessionStyle::Raw).build().await?, false).await?;
+	let bob = RawDatagramSocket::from_session(bridge.session_builder("raw_bob", SessionStyle::Raw).build().await?, true).await?;
+
+	let mut datagram = DatagramMessage::new("raw_alice", &bob.local_destination().to_base64(), b"hello".to_vec());
+	datagram.from_port = Some(1);
+	datagram.to_port = Some(2);
+	datagram.protocol = Some(19);
+	alice.send(datagram).await?;
+
+	let received = bob.recv().await?;
+	assert_eq!(received.contents, b"hello");
+	assert_eq!(received.from_port, Some(1));
+	assert_eq!(received.to_port, Some(2));
+	assert_eq!(received.protocol, Some(19));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn only_stream_sessions_connect_streams() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let server = bridge.session_builder("style_server", SessionStyle::Stream).build().await?;
+	server.listen().await?;
+
+	let session = bridge.session_builder("style_datagram", SessionStyle::Datagram).build().await?;
+
+	let error = session.connect_stream(server.public_key.clone()).await.unwrap_err();
+	assert!(matches!(error, Error::Protocol(_)));
+	assert!(!bridge.commands().iter().any(|command| command.get("ID") == Some("style_datagram")));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn signature_types_are_only_sent_with_dest_generate() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	bridge
+		.session_builder("signature_session", SessionStyle::Stream)
+		.signature_type(SignatureType::EdDsaSha512Ed25519)
+		.build()
+		.await?
+		.listen()
+		.await?;
+
+	let commands = bridge.commands();
+	let sent_with = |topic: &str| {
+		commands
+			.iter()
+			.any(|command| command.topic == topic && command.get("SIGNATURE_TYPE").is_some())
+	};
+	assert!(sent_with("DEST"));
+	assert!(!sent_with("SESSION"));
+
+	let mut config = bridge.config();
+	config.max_version = "3.0".to_owned();
+
+	let result = bridge
+		.session_builder("signature_old_bridge", SessionStyle::Stream)
+		.config(config)
+		.signature_type(SignatureType::EdDsaSha512Ed25519)
+		.build()
+		.await;
+	assert!(matches!(result, Err(Error::Protocol(_))));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn names_resolve_to_registered_and_known_destinations() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let session = bridge.session_builder("naming_session", SessionStyle::Stream).build().await?;
+	let other = bridge.session_builder("naming_other", SessionStyle::Stream).build().await?;
+	other.listen().await?;
+
+	bridge.register_name("example.i2p", other.public_key.clone());
+
+	assert_eq!(session.look_up("example.i2p").await?, other.public_key);
+	assert_eq!(session.look_up(other.address()?).await?, other.public_key);
+	assert_eq!(other.look_up("ME").await?, other.public_key);
+
+	let error = session.look_up("nowhere.i2p").await.unwrap_err();
+	assert!(error.is_not_found());
+
+	let error = session.look_up("not a name").await.unwrap_err();
+	assert_eq!(error.sam_result(), Some(&SamResult::InvalidKey));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn lookups_with_options_return_the_properties_of_names() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let session = bridge.session_builder("options_session", SessionStyle::Stream).build().await?;
+	let keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	let options = BTreeMap::from([("description".to_owned(), "an example".to_owned())]);
+	bridge.register_name_with_options("example.i2p", keys.destination().clone(), options.clone());
+
+	let lookup = session.look_up_with_options("example.i2p").await?;
+	assert_eq!(lookup.name, "example.i2p");
+	assert_eq!(&lookup.destination, keys.destination());
+	assert_eq!(lookup.options, options);
+
+	let commands = bridge.commands();
+	assert_eq!(commands.last().and_then(|command| command.get("OPTIONS")), Some("true"));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn injected_failures_are_returned_once() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	bridge.fail_next(Operation::DestGenerate, SamResult::I2pError);
+
+	let error = bridge
+		.session_builder("failing_session", SessionStyle::Stream)
+		.build()
+		.await
+		.unwrap_err();
+	assert_eq!(error.sam_result(), Some(&SamResult::I2pError));
+
+	bridge.session_builder("failing_session", SessionStyle::Stream).build().await?;
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn injected_latency_trips_timeouts() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	bridge.delay(Operation::Hello, Duration::from_millis(500));
+
+	let timeouts = Timeouts {
+		hello: Duration::from_millis(50),
+		..Timeouts::default()
+	};
+
+	let error = bridge
+		.session_builder("slow_session", SessionStyle::Stream)
+		.timeouts(timeouts)
+		.build()
+		.await
+		.unwrap_err();
+
+	assert!(matches!(error, Error::Timeout(Operation::Hello)));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn late_replies_are_not_taken_for_the_next_command() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let timeouts = Timeouts {
+		naming_lookup: Duration::from_millis(100),
+		..Timeouts::default()
+	};
+
+	let session = bridge
+		.session_builder("late_session", SessionStyle::Stream)
+		.timeouts(timeouts)
+		.build()
+		.await?;
+	session.listen().await?;
+
+	let keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	bridge.register_name("a.i2p", keys.destination().clone());
+	bridge.register_name("b.i2p", session.public_key.clone());
+	bridge.delay(Operation::NamingLookup, Duration::from_millis(300));
+
+	let error = session.look_up("a.i2p").await.unwrap_err();
+	assert!(matches!(error, Error::Timeout(Operation::NamingLookup)));
+
+	bridge.delay(Operation::NamingLookup, Duration::ZERO);
+	tokio::time::sleep(Duration::from_millis(250)).await;
+
+	assert_eq!(session.look_up("b.i2p").await?, session.public_key);
+	assert_eq!(session.look_up("a.i2p").await?, *keys.destination());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn primary_subsessions_share_the_destination() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let primary = PrimarySession::builder("primary").config(bridge.config()).build_primary().await?;
+	let subsession = primary.add("primary_stream", SessionStyle::Stream, 0);
+	let mut listener = subsession.listen().await?;
+
+	let client = bridge.session_builder("primary_client", SessionStyle::Stream).build().await?;
+
+	let accept = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await?;
+
+		let mut buffer = [0u8; 5];
+		stream.read_exact(&mut buffer).await?;
+
+		anyhow::Ok(buffer)
+	});
+
+	let mut stream = client.connect_stream(&primary.public_key).await?;
+	stream.write_all(b"hello").await?;
+
+	assert_eq!(&accept.await??, b"hello");
+
+	subsession.close().await?;
+
+	let commands = bridge.commands();
+	assert!(commands
+		.iter()
+		.any(|command| command.is("SESSION", "ADD") && command.get("ID") == Some("primary_stream")));
+	assert!(commands
+		.iter()
+		.any(|command| command.is("SESSION", "REMOVE") && command.get("ID") == Some("primary_stream")));
+
+	primary.close().await?;
+
+	Ok(())
+}
@@ -0,0 +1,59 @@
+use solitude::{Error, NamingLookup, PrivateKeys, SamMessage, SignatureType};
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+#[test]
+fn naming_reply_options_are_collected() -> Result<()> {
+	let keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	let reply = SamMessage::parse(&format!(
+		"NAMING REPLY RESULT=OK NAME=example.i2p VALUE={} OPTION:description=\"an example\" OPTION:contact=admin@example.i2p",
+		keys.destination()
+	))?;
+
+	let lookup = NamingLookup::from_reply("example.i2p", &reply)?;
+
+	assert_eq!(lookup.name, "example.i2p");
+	assert_eq!(&lookup.destination, keys.destination());
+	assert_eq!(lookup.options.get("description").map(String::as_str), Some("an example"));
+	assert_eq!(lookup.options.get("contact").map(String::as_str), Some("admin@example.i2p"));
+
+	Ok(())
+}
+
+#[test]
+fn naming_lookup_survives_a_round_trip() -> Result<()> {
+	let keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+
+	let lookup = NamingLookup {
+		name: "example.i2p".to_owned(),
+		destination: keys.destination().clone(),
+		options: BTreeMap::from([("description".to_owned(), "an example".to_owned())]),
+	};
+
+	assert_eq!(NamingLookup::from_reply("example.i2p", &lookup.to_reply())?, lookup);
+
+	Ok(())
+}
+
+#[test]
+fn replies_without_a_name_keep_the_one_looked_up() -> Result<()> {
+	let keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let reply = SamMessage::parse(&format!("NAMING REPLY RESULT=OK VALUE={}", keys.destination()))?;
+
+	assert_eq!(NamingLookup::from_reply("example.i2p", &reply)?.name, "example.i2p");
+
+	Ok(())
+}
+
+#[test]
+fn replies_for_another_name_are_refused() -> Result<()> {
+	let keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let reply = SamMessage::parse(&format!("NAMING REPLY RESULT=OK NAME=a.i2p VALUE={}", keys.destination()))?;
+
+	assert!(matches!(NamingLookup::from_reply("b.i2p", &reply), Err(Error::Protocol(_))));
+	assert!(NamingLookup::from_reply("A.i2p", &reply).is_ok());
+
+	Ok(())
+}
@@ -0,0 +1,60 @@
+use solitude::{EncryptedLeaseSet, EncryptionType, SessionOptions};
+
+use std::time::Duration;
+
+#[test]
+fn empty_options_send_nothing() {
+	assert!(SessionOptions::new().options().is_empty());
+}
+
+#[test]
+fn typed_options_use_i2cp_names() {
+	let options = SessionOptions {
+		inbound_length: Some(1),
+		outbound_length: Some(0),
+		inbound_length_variance: Some(-1),
+		inbound_quantity: Some(4),
+		inbound_nickname: Some("my service".to_owned()),
+		lease_set_encryption_types: vec![EncryptionType::X25519, EncryptionType::ElGamal],
+		reduce_on_idle: Some(true),
+		reduce_idle_time: Some(Duration::from_secs(600)),
+		..SessionOptions::default()
+	};
+
+	let options = options.options();
+	let pairs: Vec<(&str, &str)> = options.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+
+	assert_eq!(
+		pairs,
+		vec![
+			("inbound.length", "1"),
+			("outbound.length", "0"),
+			("inbound.lengthVariance", "-1"),
+			("inbound.quantity", "4"),
+			("inbound.nickname", "my service"),
+			("i2cp.leaseSetEncType", "4,0"),
+			("i2cp.reduceOnIdle", "true"),
+			("i2cp.reduceIdleTime", "600000"),
+		]
+	);
+}
+
+#[test]
+fn extra_options_override_fields() {
+	let options = SessionOptions {
+		inbound_length: Some(3),
+		encrypted_lease_set: Some(EncryptedLeaseSet::default()),
+		..SessionOptions::default()
+	}
+	.with("inbound.length", 2)
+	.with("i2p.streaming.profile", "interactive");
+
+	assert_eq!(
+		options.options(),
+		vec![
+			("i2cp.leaseSetType".to_owned(), "5".to_owned()),
+			("i2p.streaming.profile".to_owned(), "interactive".to_owned()),
+			("inbound.length".to_owned(), "2".to_owned()),
+		]
+	);
+}
@@ -0,0 +1,61 @@
+use solitude::testing::MockBridge;
+use solitude::{I2pDatagramSocket, PrimarySession, SessionStyle};
+
+use anyhow::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use env_logger::Target;
+
+/// Starts a mock bridge, so the tests run without an I2P router.
+async fn init() -> Result<MockBridge> {
+	let _ = env_logger::builder()
+		.is_test(true)
+		.format_module_path(true)
+		.target(Target::Stdout)
+		.try_init();
+
+	Ok(MockBridge::start().await?)
+}
+
+#[tokio::test]
+async fn subsessions_share_the_primary_destination() -> Result<()> {
+	let bridge = init().await?;
+
+	let primary = PrimarySession::builder("subsessions_share_the_primary_destination")
+		.config(bridge.config())
+		.build_primary()
+		.await?;
+
+	let stream_subsession = primary.add("subsessions_share_the_primary_destination_stream", SessionStyle::Stream, 0);
+	let datagram_subsession = primary.add("subsessions_share_the_primary_destination_datagram", SessionStyle::Datagram, 0);
+
+	let mut listener = stream_subsession.listen().await?;
+	let datagram_socket = I2pDatagramSocket::from_session(datagram_subsession).await?;
+
+	assert_eq!(stream_subsession.public_key, primary.public_key);
+	assert_eq!(datagram_socket.local_destination(), &primary.public_key);
+
+	let client = bridge
+		.session_builder("subsessions_share_the_primary_destination_client", SessionStyle::Stream)
+		.build()
+		.await?;
+
+	let accept = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await?;
+
+		let mut buffer = [0u8; 5];
+		stream.read_exact(&mut buffer).await?;
+
+		anyhow::Ok(buffer)
+	});
+
+	let mut stream = client.connect_stream(&primary.public_key).await?;
+	stream.write_all(b"hello").await?;
+
+	assert_eq!(&accept.await??, b"hello");
+
+	stream_subsession.close().await?;
+	primary.close().await?;
+
+	Ok(())
+}
@@ -0,0 +1,243 @@
+use solitude::testing::MockBridge;
+use solitude::{
+	AddressBook, B32Address, CachedResolver, Error, HostEntry, Operation, PrivateKeys, Resolution, Resolver, ResolverChain, SessionStyle,
+	SignatureType,
+};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn naming_lookups(bridge: &MockBridge) -> usize {
+	bridge
+		.commands()
+		.iter()
+		.filter(|command| command.topic == "NAMING" && command.subtopic.as_deref() == Some("LOOKUP"))
+		.count()
+}
+
+#[tokio::test]
+async fn chains_ask_the_address_book_before_the_router() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	let session = Arc::new(bridge.session_builder("chain_session", SessionStyle::Stream).build().await?);
+	session.listen().await?;
+
+	let local = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+	let remote = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+
+	let mut address_book = AddressBook::new();
+	address_book.insert(HostEntry::new("local.i2p", local.clone()));
+
+	bridge.register_name("local.i2p", remote.clone());
+	bridge.register_name("remote.i2p", remote.clone());
+
+	let chain = ResolverChain::new().then(address_book).then(session.clone());
+	let lookups = naming_lookups(&bridge);
+
+	assert_eq!(chain.resolve("local.i2p").await?, Some(local));
+	assert_eq!(naming_lookups(&bridge), lookups);
+
+	assert_eq!(chain.resolve("remote.i2p").await?, Some(remote));
+	assert_eq!(chain.resolve("nowhere.i2p").await?, None);
+	assert_eq!(naming_lookups(&bridge), lookups + 2);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn cached_names_are_only_looked_up_once() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	let session = bridge.session_builder("cache_session", SessionStyle::Stream).build().await?;
+	session.listen().await?;
+
+	let destination = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+	bridge.register_name("example.i2p", destination.clone());
+
+	let cache = CachedResolver::new(session);
+	let lookups = naming_lookups(&bridge);
+
+	assert_eq!(cache.resolve("example.i2p").await?, Some(destination.clone()));
+	assert_eq!(cache.resolve("EXAMPLE.i2p").await?, Some(destination.clone()));
+	assert_eq!(naming_lookups(&bridge), lookups + 1);
+
+	cache.forget("example.i2p");
+	assert_eq!(cache.resolve("example.i2p").await?, Some(destination));
+	assert_eq!(naming_lookups(&bridge), lookups + 2);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn cached_names_expire() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	let session = bridge.session_builder("expiry_session", SessionStyle::Stream).build().await?;
+	session.listen().await?;
+
+	let destination = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+	bridge.register_name("example.i2p", destination.clone());
+
+	let cache = CachedResolver::new(session)
+		.ttl(Duration::from_millis(200))
+		.negative_ttl(Duration::from_millis(200));
+	let lookups = naming_lookups(&bridge);
+
+	assert_eq!(cache.resolve("example.i2p").await?, Some(destination.clone()));
+	assert_eq!(cache.resolve("nowhere.i2p").await?, None);
+	assert_eq!(cache.resolve("example.i2p").await?, Some(destination.clone()));
+	assert_eq!(cache.resolve("nowhere.i2p").await?, None);
+	assert_eq!(naming_lookups(&bridge), lookups + 2);
+
+	tokio::time::sleep(Duration::from_millis(300)).await;
+
+	assert_eq!(cache.resolve("example.i2p").await?, Some(destination));
+	assert_eq!(cache.resolve("nowhere.i2p").await?, None);
+	assert_eq!(naming_lookups(&bridge), lookups + 4);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn resolved_names_are_cached_by_b32_address() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	let session = bridge.session_builder("b32_cache_session", SessionStyle::Stream).build().await?;
+	session.listen().await?;
+
+	let destination = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+	bridge.register_name("example.i2p", destination.clone());
+
+	let cache = CachedResolver::new(session);
+	let lookups = naming_lookups(&bridge);
+
+	assert_eq!(cache.resolve("example.i2p").await?, Some(destination.clone()));
+	assert_eq!(cache.resolve(&B32Address::from(&destination).to_string()).await?, Some(destination));
+	assert_eq!(naming_lookups(&bridge), lookups + 1);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_lookups_of_a_name_are_coalesced() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	let session = bridge.session_builder("coalescing_session", SessionStyle::Stream).build().await?;
+	session.listen().await?;
+
+	let destination = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+	bridge.register_name("example.i2p", destination.clone());
+	bridge.delay(Operation::NamingLookup, Duration::from_millis(200));
+
+	let cache = Arc::new(CachedResolver::new(session));
+	let lookups = naming_lookups(&bridge);
+
+	let resolutions = (0..10).map(|_| {
+		let cache = cache.clone();
+		tokio::spawn(async move { cache.resolve("example.i2p").await })
+	});
+
+	for resolution in resolutions.collect::<Vec<_>>() {
+		assert_eq!(resolution.await??, Some(destination.clone()));
+	}
+
+	assert_eq!(naming_lookups(&bridge), lookups + 1);
+
+	Ok(())
+}
+
+/// A resolver whose lookups always time out.
+struct Unreachable;
+
+impl Resolver for Unreachable {
+	fn resolve<'a>(&'a self, _name: &'a str) -> Resolution<'a> {
+		Box::pin(async { Err(Error::Timeout(Operation::NamingLookup)) })
+	}
+}
+
+#[tokio::test]
+async fn failed_lookups_are_not_kept() -> Result<()> {
+	let cache = CachedResolver::new(Unreachable);
+
+	for index in 0..10 {
+		assert!(cache.resolve(&format!("name{}.i2p", index)).await.is_err());
+	}
+
+	assert!(cache.is_empty());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn caches_keep_to_their_capacity() -> Result<()> {
+	let mut address_book = AddressBook::new();
+
+	for index in 0..10 {
+		let destination = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+		address_book.insert(HostEntry::new(format!("name{}.i2p", index), destination));
+	}
+
+	let cache = CachedResolver::new(address_book).capacity(4);
+
+	for index in 0..10 {
+		assert!(cache.resolve(&format!("name{}.i2p", index)).await?.is_some());
+	}
+
+	assert_eq!(cache.len(), 4);
+	assert!(cache.resolve("name9.i2p").await?.is_some());
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn forgotten_names_are_forgotten_by_b32_address_too() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+	let session = bridge.session_builder("forget_session", SessionStyle::Stream).build().await?;
+	session.listen().await?;
+
+	let destination = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?.destination().clone();
+	bridge.register_name("example.i2p", destination.clone());
+
+	let cache = CachedResolver::new(session);
+	let address = B32Address::from(&destination).to_string();
+
+	cache.resolve("example.i2p").await?;
+	cache.forget("example.i2p");
+	assert!(cache.is_empty());
+
+	let lookups = naming_lookups(&bridge);
+	assert_eq!(cache.resolve(&address).await?, Some(destination));
+	assert_eq!(naming_lookups(&bridge), lookups + 1);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn streams_connect_to_names() -> Result<()> {
+	let bridge = MockBridge::start().await?;
+
+	let server = bridge.session_builder("by_name_server", SessionStyle::Stream).build().await?;
+	let client = bridge.session_builder("by_name_client", SessionStyle::Stream).build().await?;
+
+	let mut address_book = AddressBook::new();
+	address_book.insert(HostEntry::new("server.i2p", server.public_key.clone()));
+
+	let mut listener = server.listen().await?;
+
+	let accept = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await?;
+
+		let mut buffer = [0u8; 5];
+		stream.read_exact(&mut buffer).await?;
+
+		anyhow::Ok(buffer)
+	});
+
+	let mut stream = client.connect_stream_by_name("server.i2p", &address_book).await?;
+	stream.write_all(b"hello").await?;
+
+	assert_eq!(&accept.await??, b"hello");
+
+	let error = client.connect_stream_by_name("nowhere.i2p", &address_book).await.unwrap_err();
+	assert!(error.is_not_found());
+
+	Ok(())
+}
@@ -1,34 +1,40 @@
 #[macro_use]
 extern crate log;
 
-use solitude::{Session, SessionStyle};
+use solitude::testing::MockBridge;
+use solitude::{SessionStyle, StreamInfo};
 
 use anyhow::Result;
-use std::time::Duration;
+use std::sync::Arc;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
 use tokio::net::TcpListener;
 
 use env_logger::Target;
 
-async fn init() {
+/// Starts a mock bridge, so the tests run without an I2P router.
+async fn init() -> Result<MockBridge> {
 	let _ = env_logger::builder()
 		.is_test(true)
 		.format_module_path(true)
 		.target(Target::Stdout)
 		.try_init();
 
-	tokio::time::sleep(Duration::from_secs(10)).await;
+	Ok(MockBridge::start().await?)
 }
 
 #[tokio::test]
 async fn can_create_stream_forwarding_session() -> Result<()> {
-	init().await;
+	let bridge = init().await?;
 
 	let tcp_listener = TcpListener::bind("127.0.0.1:0").await?;
 
-	let mut session = Session::new("can_create_stream_forwarding_session", SessionStyle::Stream).await?;
+	let mut session = bridge
+		.session_builder("can_create_stream_forwarding_session", SessionStyle::Stream)
+		.build()
+		.await?;
 	session.forward("127.0.0.1", tcp_listener.local_addr()?.port()).await?;
 
 	Ok(())
@@ -36,7 +42,7 @@ async fn can_create_stream_forwarding_session() -> Result<()> {
 
 #[tokio::test]
 async fn client_stream_can_send_to_listening_stream() -> Result<()> {
-	init().await;
+	let bridge = init().await?;
 
 	let test_name = "client_stream_can_send_to_listening_stream";
 
@@ -45,7 +51,7 @@ async fn client_stream_can_send_to_listening_stream() -> Result<()> {
 
 	tokio::task::spawn(async move {
 		debug!("awaiting connections");
-		for (stream, _address) in tcp_listener.accept().await {
+		while let Ok((stream, _address)) = tcp_listener.accept().await {
 			debug!("received connection");
 
 			let mut buffer = String::new();
@@ -55,15 +61,174 @@ async fn client_stream_can_send_to_listening_stream() -> Result<()> {
 		}
 	});
 
-	let mut session = Session::new(test_name, SessionStyle::Stream).await?;
+	let mut session = bridge.session_builder(test_name, SessionStyle::Stream).build().await?;
 	session.forward("127.0.0.1", port).await?;
 
 	let client_stream_session_name = format!("{}_client", test_name);
 
-	let mut client_stream = Session::new(client_stream_session_name, SessionStyle::Stream).await?;
+	let client_stream = bridge
+		.session_builder(client_stream_session_name, SessionStyle::Stream)
+		.build()
+		.await?;
 	let mut tcp_stream = client_stream.connect_stream(session.public_key).await?;
 
 	tcp_stream.write_all("Hello World!".as_bytes()).await?;
 
 	Ok(())
 }
+
+#[tokio::test]
+async fn listener_accepts_client_stream() -> Result<()> {
+	let bridge = init().await?;
+
+	let test_name = "listener_accepts_client_stream";
+
+	let session = bridge.session_builder(test_name, SessionStyle::Stream).build().await?;
+	let mut listener = session.listen().await?;
+
+	let client_session = bridge
+		.session_builder(format!("{}_client", test_name), SessionStyle::Stream)
+		.build()
+		.await?;
+	let mut client_stream = client_session.connect_stream(session.public_key.clone()).await?;
+
+	client_stream.write_all("Hello World!\n".as_bytes()).await?;
+	client_stream.flush().await?;
+
+	let (stream, peer_destination) = listener.accept().await?;
+	assert_eq!(peer_destination, client_session.public_key);
+	assert_eq!(stream.peer_destination(), &client_session.public_key);
+	assert_eq!(stream.local_destination(), &session.public_key);
+
+	let mut buffer = String::new();
+	BufReader::new(stream).read_line(&mut buffer).await?;
+	assert_eq!(buffer, "Hello World!\n");
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn session_can_open_several_streams() -> Result<()> {
+	let bridge = init().await?;
+
+	let test_name = "session_can_open_several_streams";
+
+	let session = bridge.session_builder(test_name, SessionStyle::Stream).build().await?;
+	let mut listener = session.listen().await?;
+
+	let client_session = bridge
+		.session_builder(format!("{}_client", test_name), SessionStyle::Stream)
+		.build()
+		.await?;
+	let mut first_stream = client_session.connect_stream(session.public_key.clone()).await?;
+	let mut second_stream = client_session.connect_stream(session.public_key.clone()).await?;
+
+	first_stream.write_all("first\n".as_bytes()).await?;
+	second_stream.write_all("second\n".as_bytes()).await?;
+
+	for _ in 0..2 {
+		let (_stream, peer_destination) = listener.accept().await?;
+		assert_eq!(peer_destination, client_session.public_key);
+	}
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn can_deserialize_stream_info_with_ports() -> Result<()> {
+	let mut header = &b"test_destination FROM_PORT=1234 TO_PORT=80\nHello World!"[..];
+	let stream_info = StreamInfo::from_bufread(&mut header).await?;
+
+	assert_eq!(stream_info.destination, "test_destination");
+	assert_eq!(stream_info.from_port, Some(1234));
+	assert_eq!(stream_info.to_port, Some(80));
+	assert_eq!(header, b"Hello World!");
+
+	let mut header = &b"test_destination\n"[..];
+	assert_eq!(StreamInfo::from_bufread(&mut header).await?, StreamInfo::new("test_destination"));
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn stream_info_round_trips() -> Result<()> {
+	let mut stream_info = StreamInfo::new("test_destination");
+	stream_info.from_port = Some(1234);
+	stream_info.to_port = Some(80);
+
+	let header = stream_info.serialize();
+	assert_eq!(header, "test_destination FROM_PORT=1234 TO_PORT=80\n");
+	assert_eq!(StreamInfo::from_bufread(&mut header.as_bytes()).await?, stream_info);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_streams_reuse_one_session() -> Result<()> {
+	let bridge = init().await?;
+
+	let server = bridge.session_builder("concurrent_server", SessionStyle::Stream).build().await?;
+	let mut listener = server.listen().await?;
+
+	tokio::spawn(async move {
+		while let Ok((mut stream, _)) = listener.accept().await {
+			tokio::spawn(async move {
+				let mut buffer = [0u8; 1024];
+
+				while let Ok(length) = stream.read(&mut buffer).await {
+					if length == 0 || stream.write_all(&buffer[..length]).await.is_err() {
+						break;
+					}
+				}
+			});
+		}
+	});
+
+	let session = Arc::new(
+		bridge
+			.session_builder("concurrent_streams_reuse_one_session", SessionStyle::Stream)
+			.build()
+			.await?,
+	);
+
+	let peer = server.public_key.clone();
+
+	let connects = (0..8).map(|index| {
+		let session = session.clone();
+		let peer = peer.clone();
+
+		tokio::spawn(async move {
+			let mut stream = session.connect_stream(&peer).await?;
+			let message = format!("stream {}", index);
+
+			stream.write_all(message.as_bytes()).await?;
+
+			let mut echo = vec![0u8; message.len()];
+			stream.read_exact(&mut echo).await?;
+
+			assert_eq!(echo, message.as_bytes());
+			assert_eq!(stream.peer_destination(), &peer);
+
+			anyhow::Ok(())
+		})
+	});
+
+	for connect in connects.collect::<Vec<_>>() {
+		connect.await??;
+	}
+
+	session.connect_stream(&peer).await?;
+
+	let session_creates = bridge
+		.commands()
+		.into_iter()
+		.filter(|command| {
+			command.topic == "SESSION"
+				&& command.subtopic.as_deref() == Some("CREATE")
+				&& command.get("ID") == Some("concurrent_streams_reuse_one_session")
+		})
+		.count();
+	assert_eq!(session_creates, 1);
+
+	Ok(())
+}
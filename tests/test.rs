@@ -1,26 +1,26 @@
-use solitude::{Session, SessionStyle};
-
-use std::time::Duration;
+use solitude::testing::MockBridge;
+use solitude::{PrivateKeys, Session, SessionStyle, SignatureType};
 
 use anyhow::Result;
 
 use env_logger::Target;
 
-async fn init() {
+/// Starts a mock bridge, so the tests run without an I2P router.
+async fn init() -> Result<MockBridge> {
 	let _ = env_logger::builder()
 		.is_test(true)
 		.format_module_path(true)
 		.target(Target::Stdout)
 		.try_init();
 
-	tokio::time::sleep(Duration::from_secs(10)).await;
+	Ok(MockBridge::start().await?)
 }
 
 #[tokio::test]
 async fn service_can_be_resolved() -> Result<()> {
-	init().await;
+	let bridge = init().await?;
 
-	let (session, mut second_session) = create_two_sessions("service_can_be_resolved", SessionStyle::Datagram, 0, 0).await?;
+	let (session, second_session) = create_two_sessions(&bridge, "service_can_be_resolved", SessionStyle::Datagram, 0, 0).await?;
 
 	let session_address = session.address()?;
 	let name = second_session.look_up(session_address.clone()).await?;
@@ -31,33 +31,79 @@ async fn service_can_be_resolved() -> Result<()> {
 
 #[tokio::test]
 async fn session_can_be_restored() -> Result<()> {
-	init().await;
+	let bridge = init().await?;
 
 	let test_name = "session_can_be_restored";
 
 	let (address, public_key, private_key) = {
-		let session = Session::new(test_name, SessionStyle::Stream).await?;
+		let session = bridge.session_builder(test_name, SessionStyle::Stream).build().await?;
 
 		(session.address()?, session.public_key, session.private_key)
 	};
 
-	let session = Session::from(format!("{}_restore", test_name), SessionStyle::Stream, public_key, private_key).await?;
+	let session = bridge
+		.session_builder(format!("{}_restore", test_name), SessionStyle::Stream)
+		.keys(private_key)
+		.build()
+		.await?;
 
 	assert!(address == session.address()?);
+	assert_eq!(session.public_key, public_key);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn session_accepts_generated_keys() -> Result<()> {
+	let bridge = init().await?;
+
+	let private_keys = PrivateKeys::generate(SignatureType::EdDsaSha512Ed25519)?;
+	let address = private_keys.destination().b32_address().to_string();
+
+	let session = bridge
+		.session_builder("session_accepts_generated_keys", SessionStyle::Stream)
+		.keys(private_keys)
+		.build()
+		.await?;
+	let _listener = session.listen().await?;
+
+	assert_eq!(session.address()?, address);
+
+	Ok(())
+}
+
+#[tokio::test]
+async fn session_generates_requested_signature_type() -> Result<()> {
+	let bridge = init().await?;
+
+	let session = bridge
+		.session_builder("session_generates_requested_signature_type", SessionStyle::Stream)
+		.signature_type(SignatureType::EdDsaSha512Ed25519)
+		.build()
+		.await?;
+
+	assert_eq!(session.public_key.signature_type(), SignatureType::EdDsaSha512Ed25519);
 
 	Ok(())
 }
 
 async fn create_two_sessions(
+	bridge: &MockBridge,
 	test_name: &str,
 	session_style: SessionStyle,
 	first_port: u16,
 	second_port: u16,
 ) -> Result<(Session, Session)> {
-	let mut first_session = Session::new(format!("{}_first", test_name), session_style).await?;
+	let mut first_session = bridge
+		.session_builder(format!("{}_first", test_name), session_style)
+		.build()
+		.await?;
 	first_session.forward("127.0.0.1", first_port).await?;
 
-	let mut second_session = Session::new(format!("{}_second", test_name), session_style).await?;
+	let mut second_session = bridge
+		.session_builder(format!("{}_second", test_name), session_style)
+		.build()
+		.await?;
 	second_session.forward("127.0.0.1", second_port).await?;
 
 	Ok((first_session, second_session))